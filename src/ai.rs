@@ -2,52 +2,328 @@
 use crate::user_defined::*;
 use crate::constants::*;
 use crate::helper::*;
+use crate::spells::heal;
+use crate::mapgen::{make_monster, scale_monster};
 
 use tcod::colors::{self};
-use tcod::map::{Map as FovMap}; // the 'Map as FovMap' section renames the tcod fov map
-                                // so that it doesn't conflict with our user defined Map
 use rand::Rng;
+use std::cmp;
 
-pub fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) {
-    // a basic monster takes its turn. If you can see it, it can see you
+pub fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut Vec<Object>, fov_map: &dyn FieldOfView) {
+    // a monster takes its turn, according to its ai
     use Ai::*;
     if let Some(ai) = objects[monster_id].ai.take() {
+        // a scavenger that's awake may spend its turn on something it picked up
+        let awake = match ai {
+            Sleeping{..} | Frozen{..} | Confused{..} | Escaped => false,
+            _ => true,
+        };
+        if awake && monster_use_item(monster_id, game, objects, fov_map) {
+            objects[monster_id].ai = Some(ai);
+            return;
+        }
         let new_ai = match ai {
-            Basic => ai_basic(monster_id, game, objects, fov_map),
+            Basic{path, last_seen, search_turns} => ai_basic(
+                monster_id, game, objects, fov_map, path, last_seen, search_turns),
             Confused{previous_ai, num_turns} => ai_confused (
-                monster_id, game, objects, previous_ai, num_turns)
+                monster_id, game, objects, previous_ai, num_turns),
+            Thief => ai_thief(monster_id, game, objects),
+            Fleeing => ai_fleeing(monster_id, game, objects, fov_map),
+            Escaped => Escaped,
+            Ranged => ai_ranged(monster_id, game, objects),
+            Sleeping{ai_when_awake} => ai_sleeping(monster_id, game, objects, fov_map, ai_when_awake),
+            Frozen{previous_ai, num_turns} => ai_frozen(monster_id, game, objects, fov_map, previous_ai, num_turns),
+            Pack{path, last_seen, search_turns} => ai_pack(
+                monster_id, game, objects, fov_map, path, last_seen, search_turns),
+            Summoner{cooldown} => ai_summoner(monster_id, game, objects, fov_map, cooldown),
         };
         objects[monster_id].ai = Some(new_ai);
     }
 }
 
-pub fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) -> Ai {
-    // a basic monster takes its turn. If you can see it, it can see you
-    let (monster_x, monster_y) = objects[monster_id].pos();
-    if fov_map.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
-            // move towards player if far away
-            let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, game, objects);
+/// a hurt monster drinks a healing potion it's carrying. returns true if it used up its turn
+fn monster_use_item(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView) -> bool {
+    let monster = &objects[monster_id];
+    let hurt = monster.fighter.map_or(false, |f| f.hp < monster.max_hp(game) / 2);
+    let potion = monster.inventory.iter().position(|item| item.item == Some(Item::Heal));
+    match potion {
+        Some(index) if hurt => {
+            let potion = objects[monster_id].inventory.remove(index);
+            let (x, y) = objects[monster_id].pos();
+            if fov_map.is_in_fov(x, y) {
+                game.log.add(format!("The {} drinks a {}!", objects[monster_id].name, potion.name), colors::ORANGE);
+            }
+            heal(monster_id, objects, game);
+            true
+        },
+        _ => false,
+    }
+}
+
+/// whether a monster can see the player, with its own eyes rather than through the player's fov.
+/// a player standing in the light is seen as far as the monster's sight radius reaches, one hiding
+/// in the dark only from right next to them. the sight line is only traced for monsters close enough
+pub fn can_see_player(monster_id: usize, game: &Game, objects: &[Object]) -> bool {
+    let player = &objects[PLAYER];
+    let monster = &objects[monster_id];
+    let sight_range = if game.map[player.x as usize][player.y as usize].lit {
+        monster.fov_radius
+    } else {
+        MONSTER_SIGHT_RANGE_DARK.min(monster.fov_radius)
+    };
+    game_distance(game, monster.pos(), player.pos()) <= sight_range &&
+        line_of_sight(&game.map, monster.pos(), player.pos())
+}
+
+/// whether the player is in the dark with no awake monster close enough to notice them
+pub fn player_hidden(game: &Game, objects: &[Object]) -> bool {
+    let player = &objects[PLAYER];
+    if game.map[player.x as usize][player.y as usize].lit {
+        return false;
+    }
+    !objects.iter().enumerate().any(|(id, object)| {
+        let awake = match object.ai {
+            Some(Ai::Sleeping{..}) | None => false,
+            _ => true,
+        };
+        object.faction == Faction::Hostile && object.fighter.is_some() && awake && can_see_player(id, game, objects)
+    })
+}
+
+/// whether a monster is hurt badly enough to run from the player
+fn badly_wounded(monster: &Object, game: &Game) -> bool {
+    monster.fighter.map_or(false, |f| (f.hp as f32) < monster.max_hp(game) as f32 * FLEE_HP_FRACTION)
+}
+
+pub fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView,
+    path: Vec<(i32, i32)>, last_seen: Option<(i32, i32)>, search_turns: i32) -> Ai {
+    let mut path = path;
+
+    // a badly hurt monster tries to run, unless it has nowhere to go
+    if badly_wounded(&objects[monster_id], game) {
+        let new_ai = ai_fleeing(monster_id, game, objects, fov_map);
+        let (monster_x, monster_y) = objects[monster_id].pos();
+        if new_ai == Ai::Fleeing && fov_map.is_in_fov(monster_x, monster_y) {
+            game.log.add(format!("The {} turns to flee!", objects[monster_id].name), colors::LIGHT_BLUE);
+        }
+        return new_ai
+    }
+
+    // a basic monster takes its turn, if it can see the player
+    if can_see_player(monster_id, game, objects) {
+        // remember where the player was, in case they slip away
+        let player_pos = objects[PLAYER].pos();
+        let last_seen = Some(player_pos);
+        if game_distance(game, objects[monster_id].pos(), objects[PLAYER].pos()) >= 2.0 {
+            // move towards player if far away, walking around walls rather than into them
+            path = follow_path(monster_id, player_pos, path, game, objects);
         } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
             // close enough, attack! (if the player is still alive)
             let (monster, player) = mut_two(monster_id, PLAYER, objects);
             monster.attack(player, game);
         }
+        return Ai::Basic{path: path, last_seen: last_seen, search_turns: MONSTER_MEMORY_TURNS}
+    }
+
+    // lost sight of the player, so head for where they were last seen for a while
+    if let Some(last_seen) = last_seen {
+        if search_turns > 0 && objects[monster_id].pos() != last_seen {
+            path = follow_path(monster_id, last_seen, path, game, objects);
+            if objects[monster_id].pos() != last_seen {
+                return Ai::Basic{path: path, last_seen: Some(last_seen), search_turns: search_turns - 1}
+            }
+        }
+    }
+    // the trail has gone cold, give up and wait
+    Ai::basic()
+}
+
+pub fn ai_pack(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView,
+    path: Vec<(i32, i32)>, last_seen: Option<(i32, i32)>, search_turns: i32) -> Ai {
+    // pack mates are the same kind of monster, hunting as a pack and in sight of this one
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let mut pack: Vec<usize> = (0..objects.len()).filter(|&id| {
+        let object = &objects[id];
+        id != monster_id && object.alive && object.name == objects[monster_id].name &&
+            match object.ai { Some(Ai::Pack{..}) => true, _ => false } &&
+            game_distance(game, (monster_x, monster_y), object.pos()) <= PACK_RANGE &&
+            line_of_sight(&game.map, (monster_x, monster_y), object.pos())
+    }).collect();
+
+    let player_pos = objects[PLAYER].pos();
+    let hunting = can_see_player(monster_id, game, objects) &&
+        game_distance(game, (monster_x, monster_y), player_pos) >= 2.0;
+    if pack.is_empty() || !hunting || badly_wounded(&objects[monster_id], game) {
+        // alone, out of sight, already close enough to attack, or hurt: just act like a basic monster
+        return match ai_basic(monster_id, game, objects, fov_map, path, last_seen, search_turns) {
+            Ai::Basic{path, last_seen, search_turns} => Ai::Pack{path: path, last_seen: last_seen, search_turns: search_turns},
+            other => other,
+        }
+    }
+
+    // share out the free tiles around the player, each member in turn taking the
+    // closest one left, so the pack surrounds the player instead of queueing up
+    pack.push(monster_id);
+    pack.sort();
+    let index = ObjectIndex::new(objects);
+    let mut free_tiles = vec![];
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (player_pos.0 + dx, player_pos.1 + dy);
+            if (dx, dy) != (0, 0) && !(game.settings.orthogonal_movement && dx != 0 && dy != 0) &&
+                !index.is_blocked(x, y, &game.map, objects) &&
+                game.map[x as usize][y as usize].terrain != Terrain::Lava {
+                free_tiles.push((x, y));
+            }
+        }
+    }
+    let mut goal = player_pos;
+    for id in pack {
+        if game_distance(game, objects[id].pos(), player_pos) < 2.0 {
+            continue; // already next to the player
+        }
+        let closest = (0..free_tiles.len()).min_by_key(|&i| {
+            let (x, y) = free_tiles[i];
+            (objects[id].x - x).pow(2) + (objects[id].y - y).pow(2)
+        });
+        if let Some(i) = closest {
+            let tile = free_tiles.swap_remove(i);
+            if id == monster_id {
+                goal = tile;
+                break;
+            }
+        }
+    }
+
+    // no free tile left means the player is surrounded already, so just close in
+    let path = follow_path(monster_id, goal, path, game, objects);
+    Ai::Pack{path: path, last_seen: Some(player_pos), search_turns: MONSTER_MEMORY_TURNS}
+}
+
+pub fn ai_ranged(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    // an archer keeps the player at arm's length and shoots when it has a clear line
+    if !can_see_player(monster_id, game, objects) {
+        return Ai::Ranged
+    }
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let distance = game_distance(game, (monster_x, monster_y), (player_x, player_y));
+    let player_alive = objects[PLAYER].fighter.map_or(false, |f| f.hp > 0);
+
+    if distance < 2.0 {
+        // too close to shoot, so back off to get some room; if cornered, fight in melee
+        if !move_away(monster_id, player_x, player_y, game, objects) && player_alive {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+        }
+    } else if distance <= RANGED_ATTACK_RANGE && line_of_sight(&game.map, (monster_x, monster_y), (player_x, player_y)) {
+        // the shot is traced from the archer itself, not from the player's fov, which depends on their torch
+        if player_alive {
+            game.animations.push(Animation::Projectile{from: (monster_x, monster_y), to: (player_x, player_y),
+                char: '-', color: colors::LIGHT_SEPIA});
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            let damage = monster.power(game) - player.defense(game);
+            if damage > 0 {
+                game.log.add(format!("The {}'s arrow hits you for {} hit points.", monster.name, damage), colors::RED);
+                player.take_damage(damage, game);
+            } else {
+                game.log.add(format!("The {}'s arrow glances off you.", monster.name), colors::WHITE);
+            }
+        }
+    } else {
+        // out of range, so close in
+        move_towards(monster_id, player_x, player_y, game, objects);
+    }
+    Ai::Ranged
+}
+
+pub fn ai_summoner(monster_id: usize, game: &mut Game, objects: &mut Vec<Object>, fov_map: &dyn FieldOfView,
+    cooldown: i32) -> Ai {
+    // a summoner stays where it is, calling up minions while it can see the player
+    let cooldown = cmp::max(0, cooldown - 1);
+    if !can_see_player(monster_id, game, objects) {
+        return Ai::Summoner{cooldown: cooldown}
+    }
+    let summons = objects.iter().filter(|object| object.summoned && object.alive).count();
+    if cooldown == 0 && summons < SUMMON_CAP && summon(monster_id, game, objects, fov_map) {
+        return Ai::Summoner{cooldown: SUMMON_COOLDOWN}
+    }
+    // if the player gets next to it, it fights back
+    let player_alive = objects[PLAYER].fighter.map_or(false, |f| f.hp > 0);
+    if game_distance(game, objects[monster_id].pos(), objects[PLAYER].pos()) < 2.0 && player_alive {
+        let (monster, player) = mut_two(monster_id, PLAYER, objects);
+        monster.attack(player, game);
+    }
+    Ai::Summoner{cooldown: cooldown}
+}
+
+/// put a rat on a free tile next to a summoner. returns false if there was no room for one.
+/// the rat is added at the end of the objects, so it takes its first turn next turn
+fn summon(monster_id: usize, game: &mut Game, objects: &mut Vec<Object>, fov_map: &dyn FieldOfView) -> bool {
+    let (x, y) = objects[monster_id].pos();
+    let mut free = vec![];
+    for dx in -1..2 {
+        for dy in -1..2 {
+            // never onto lava, where the rat would burn up straight away
+            if !is_blocked(x + dx, y + dy, &game.map, objects) &&
+                game.map[(x + dx) as usize][(y + dy) as usize].terrain != Terrain::Lava {
+                free.push((x + dx, y + dy));
+            }
+        }
+    }
+    if free.is_empty() {
+        return false;
+    }
+    let (summon_x, summon_y) = free[rand::thread_rng().gen_range(0, free.len())];
+    let mut rat = make_monster("rat", summon_x, summon_y, game.dungeon_level);
+    scale_monster(&mut rat, game.dungeon_level, game.difficulty);
+    rat.alive = true;
+    rat.summoned = true;
+    objects.push(rat);
+    if fov_map.is_in_fov(x, y) {
+        game.log.add(format!("The {} calls up a rat!", objects[monster_id].name), colors::VIOLET);
+    }
+    true
+}
+
+pub fn ai_sleeping(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView,
+    ai_when_awake: Box<Ai>) -> Ai {
+    // a sleeping monster stays put until the player gets too close
+    if game_distance(game, objects[monster_id].pos(), objects[PLAYER].pos()) > WAKE_RADIUS {
+        return Ai::Sleeping{ai_when_awake: ai_when_awake}
+    }
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if fov_map.is_in_fov(monster_x, monster_y) {
+        game.log.add(format!("The {} wakes up!", objects[monster_id].name), colors::LIGHT_BLUE);
     }
-    Ai::Basic
+    *ai_when_awake
 }
 
 pub fn ai_confused(monster_id: usize, game: &mut Game, objects: &mut [Object],
     previous_ai: Box<Ai>, num_turns: i32) -> Ai {
     if num_turns >= 0 {
         // still confused...
-        // move in a random direction, and decrease the number of turns confused
-        move_by(monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            game, 
-            objects);
+        // stumble in a random open direction (or stand there dazed), and decrease the number of turns confused
+        let (x, y) = objects[monster_id].pos();
+        let orthogonal = game.settings.orthogonal_movement;
+        let mut steps = vec![(0, 0)];
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (step_x, step_y) = (x + dx, y + dy);
+                if (dx, dy) != (0, 0) && !(orthogonal && dx != 0 && dy != 0) &&
+                    !is_blocked(step_x, step_y, &game.map, objects) &&
+                    game.map[step_x as usize][step_y as usize].terrain != Terrain::Lava {
+                    steps.push((dx, dy));
+                }
+            }
+        }
+        let (dx, dy) = steps[rand::thread_rng().gen_range(0, steps.len())];
+        move_by(monster_id, dx, dy, game, objects);
+
+        if rand::thread_rng().gen_range(0, 100) < CONFUSED_FLAVOR_CHANCE && can_see_player(monster_id, game, objects) {
+            let flavor = if (dx, dy) == (0, 0) { "babbles incoherently" } else { "stumbles around in a daze" };
+            game.log.add(format!("The {} {}.", objects[monster_id].name, flavor), colors::LIGHT_BLUE);
+        }
         Ai::Confused{previous_ai: previous_ai, num_turns: num_turns - 1}
     } else {
         // restore the previous AI (this one will be deleted)
@@ -56,3 +332,140 @@ pub fn ai_confused(monster_id: usize, game: &mut Game, objects: &mut [Object],
         *previous_ai
     }
 }
+
+pub fn ai_frozen(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView,
+    previous_ai: Box<Ai>, num_turns: i32) -> Ai {
+    if num_turns > 0 {
+        // still frozen solid, so just wait it out
+        Ai::Frozen{previous_ai: previous_ai, num_turns: num_turns - 1}
+    } else {
+        // thawed out, back to whatever it was doing
+        let (monster_x, monster_y) = objects[monster_id].pos();
+        if fov_map.is_in_fov(monster_x, monster_y) {
+            game.log.add(format!("The {} thaws out!", objects[monster_id].name), colors::RED);
+        }
+        *previous_ai
+    }
+}
+
+pub fn ai_thief(monster_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    // a thief closes in like a basic monster, but steals instead of attacking
+    if can_see_player(monster_id, game, objects) {
+        if game_distance(game, objects[monster_id].pos(), objects[PLAYER].pos()) >= 2.0 {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            move_towards(monster_id, player_x, player_y, game, objects);
+        } else if game.inventory.iter().any(|item| !item.is_stuck()) {
+            // grab something at random and run for it. cursed gear won't come off
+            let loose: Vec<_> = (0..game.inventory.len()).filter(|&i| !game.inventory[i].is_stuck()).collect();
+            let index = loose[rand::thread_rng().gen_range(0, loose.len())];
+            let mut item = take_from_inventory(index, game);
+            if item.equipment.is_some() {
+                item.dequip(&mut game.log);
+            }
+            game.log.add(format!("The {} snatches your {} and runs!",
+                objects[monster_id].name, item.name), colors::RED);
+            objects[monster_id].inventory.push(item);
+            return Ai::Fleeing
+        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+            // nothing to steal, so settle for an attack
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+        }
+    }
+    Ai::Thief
+}
+
+pub fn ai_fleeing(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView) -> Ai {
+    // run directly away from the player
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let (player_x, player_y) = objects[PLAYER].pos();
+
+    // a monster carrying loot that gets far enough away unseen escapes the level with it
+    let distance = game_distance(game, objects[monster_id].pos(), objects[PLAYER].pos());
+    if !objects[monster_id].inventory.is_empty() && !fov_map.is_in_fov(monster_x, monster_y)
+        && distance >= THIEF_ESCAPE_DISTANCE {
+        let loot = objects[monster_id].inventory.iter()
+            .map(|item| item.name.clone())
+            .collect::<Vec<_>>();
+        game.log.add(format!("You hear the {} escape with your {}.",
+            objects[monster_id].name, loot.join(", ")), colors::RED);
+        return Ai::Escaped
+    }
+
+    // a wounded monster with nothing to protect stops running once it has recovered
+    let carrying_loot = !objects[monster_id].inventory.is_empty();
+    if !carrying_loot && !badly_wounded(&objects[monster_id], game) {
+        return Ai::basic()
+    }
+
+    if !move_away(monster_id, player_x, player_y, game, objects) {
+        // cornered, so turn and fight
+        if distance < 2.0 && objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+        }
+        if !carrying_loot {
+            return Ai::basic()
+        }
+    }
+    Ai::Fleeing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::tests::game_with_map;
+
+    const LONG_HALL: &[&str] = &[
+        "##################",
+        "#................#",
+        "#................#",
+        "#.....#..........#",
+        "##################",
+    ];
+
+    // the player at one spot and an orc at another, in the long hall
+    fn player_and_orc(player: (i32, i32), orc: (i32, i32)) -> Vec<Object> {
+        let mut objects = vec![crate::new_player(), make_monster("orc", orc.0, orc.1, 1)];
+        objects[PLAYER].set_pos(player.0, player.1);
+        objects
+    }
+
+    #[test]
+    fn a_player_in_the_light_is_seen_as_far_as_the_monster_sees() {
+        let mut game = game_with_map(LONG_HALL);
+        game.map[1][1].lit = true;
+        let range = make_monster("orc", 0, 0, 1).fov_radius as i32;
+        assert!(can_see_player(1, &game, &player_and_orc((1, 1), (1 + range, 1))));
+        assert!(!can_see_player(1, &game, &player_and_orc((1, 1), (2 + range, 1))));
+    }
+
+    #[test]
+    fn a_player_in_the_dark_is_only_seen_from_close_by() {
+        let game = game_with_map(LONG_HALL);
+        assert!(can_see_player(1, &game, &player_and_orc((1, 1), (2, 1))));
+        assert!(can_see_player(1, &game, &player_and_orc((1, 1), (2, 2))));
+        assert!(!can_see_player(1, &game, &player_and_orc((1, 1), (3, 1))));
+    }
+
+    #[test]
+    fn a_short_sighted_monster_sees_no_further_in_the_dark() {
+        let game = game_with_map(LONG_HALL);
+        let mut objects = player_and_orc((1, 1), (2, 2));
+        objects[1].fov_radius = 1.0;
+        // the diagonal is further than it can see, even though the dark range would reach
+        assert!(!can_see_player(1, &game, &objects));
+        objects[1].set_pos(2, 1);
+        assert!(can_see_player(1, &game, &objects));
+    }
+
+    #[test]
+    fn a_wall_between_hides_a_lit_player() {
+        let mut game = game_with_map(LONG_HALL);
+        game.map[3][3].lit = true;
+        assert!(!can_see_player(1, &game, &player_and_orc((3, 3), (9, 3))));
+        // stepping out from behind the wall
+        game.map[3][2].lit = true;
+        assert!(can_see_player(1, &game, &player_and_orc((3, 2), (9, 3))));
+    }
+}
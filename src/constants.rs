@@ -9,12 +9,17 @@ pub const SCREEN_WIDTH: i32 = 80;
 pub const SCREEN_HEIGHT: i32 = 60;
 pub const LIMIT_FPS: i32 = 20; // limit frames per second
 
-pub const MAP_WIDTH: i32 = 80;
-pub const MAP_HEIGHT: i32 = 53;
+pub const MAP_WIDTH: i32 = 100;
+pub const MAP_HEIGHT: i32 = 70;
+
+// the part of the map shown on screen, above the panel. the camera follows the player
+pub const CAMERA_WIDTH: i32 = SCREEN_WIDTH;
+pub const CAMERA_HEIGHT: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
 // sizes and coordinates relvant for the GUI
 pub const BAR_WIDTH: i32 = 20;
 pub const PANEL_HEIGHT: i32 = 7;
+pub const MONSTER_HP_BAR_WIDTH: i32 = 3;
 pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
 
 pub const MSG_X: i32 = BAR_WIDTH + 2;
@@ -26,27 +31,100 @@ pub const INVENTORY_WIDTH: i32 = 50;
 // parameters for dungeon generator
 pub const ROOM_MAX_SIZE: i32 = 10;
 pub const ROOM_MIN_SIZE: i32 = 6;
-pub const MAX_ROOMS: i32 = 30;
+pub const MAX_ROOMS: i32 = 50;
+pub const SAFE_ROOM_CHANCE: u32 = 25; // percent of rooms left without monsters
+pub const DANGER_ROOM_CHANCE: u32 = 15; // percent of rooms that get a bigger share
+pub const DANGER_ROOM_SHARE: u32 = 4; // how many times the normal share a danger room gets
+pub const GROUP_SPAWN_CHANCE: u32 = 30; // percent chance a room's monsters come as a group
+pub const GROUP_PLACEMENT_TRIES: u32 = 10; // attempts to find a free tile for each group member
+pub const VAULT_CHANCE: u32 = 5; // percent chance each room is tried as a vault, at most one per level
+
+// parameters for the cave generator, used for every other level from CAVE_MIN_LEVEL down
+pub const CAVE_MIN_LEVEL: u32 = 3;
+pub const CAVE_WALL_CHANCE: u32 = 45; // percent of tiles that start out as wall
+pub const CAVE_SMOOTHING_STEPS: u32 = 5;
+pub const CAVE_AREA_SIZE: i32 = 10; // caves are stocked in squares this big, as if they were rooms
 
 pub const COLOR_DARK_WALL: Color = Color{r: 0, g: 0, b: 100};
 pub const COLOR_LIGHT_WALL: Color = Color{r: 130, g: 110, b: 50};
 pub const COLOR_DARK_GROUND: Color = Color{r: 50, g: 50, b: 150};
 pub const COLOR_LIGHT_GROUND: Color = Color{r: 200, g: 180, b: 50};
+pub const COLOR_WATER: Color = Color{r: 30, g: 80, b: 200};
+pub const COLOR_LAVA: Color = Color{r: 230, g: 70, b: 10};
+pub const TERRAIN_TINT_COLOR: f32 = 0.6; // how strongly water and lava show through, when the player is lit
+pub const TERRAIN_TINT_GREY: f32 = 0.25; // and in the greyscale of the dark
 
 //fov
 pub const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic;
 pub const FOV_LIGHT_WALLS: bool = true; // light walls or not
 pub const TORCH_RADIUS_IN_DARK_AREA: i32 = 10;
 pub const TORCH_RADIUS_IN_LIT_AREA: i32 = 3; // torch radius decreased if standing in light
+// eyes contract from fully dark-adjusted to lit in about three turns, and dilate one tile per turn
+pub const EYE_CONTRACT_RATE: f32 = (TORCH_RADIUS_IN_DARK_AREA - TORCH_RADIUS_IN_LIT_AREA) as f32 / 3.0;
+pub const EYE_DILATE_RATE: f32 = 1.0;
+pub const DAZZLE_THRESHOLD: f32 = 8.0; // eyes opened at least this wide are dazzled by stepping into the light
+pub const DAZZLE_TURNS: i32 = 1;
+pub const DAZZLE_RADIUS: i32 = 1; // how far a dazzled player can see
+pub const DAZZLE_WHITEN: f32 = 0.7;
+pub const CARRIED_TORCH_RADIUS: i32 = 4;
+pub const CARRIED_TORCH_TURNS: i32 = 150;
+pub const TORCH_FUEL_MIN: i32 = 300;
+pub const TORCH_FUEL_MAX: i32 = 600;
+pub const TORCH_FUEL_FALLOFF_PER_LEVEL: f32 = 0.1; // torches deeper down burn out sooner
+pub const START_TORCH_LEVELS: u32 = 3; // down to this level, there's always a torch in the room the player arrives in
+pub const LIT_THRESHOLD: f32 = 0.15; // tiles with less light than this count as dark
+pub const TARGET_OVERLAY_BLEND: f32 = 0.4; // how strongly the targeting overlay tints the map
+pub const MAX_LIGHT_BLEND: f32 = 0.8; // how far a tile right by a torch blends towards the torch color
+pub const AFTERIMAGE_FRAMES: i32 = 4; // frames a tile takes to fade out after its light goes
+pub const TORCH_FLICKER_FUEL: i32 = 20; // torches with less fuel than this flicker
+pub const LIGHT_CACHE_SIZE: usize = 256; // emitter fovs remembered before the cache is cleared
 
 // items
 pub const HEAL_AMOUNT: i32 = 40;
+pub const REGEN_BASE_RATE: i32 = 1; // regen progress gained each turn, before equipment bonuses
+pub const REGEN_THRESHOLD: i32 = 10; // regen progress needed to heal 1 hp
 pub const LIGHTNING_DAMAGE: i32 = 40;
 pub const LIGHTNING_RANGE: i32 = 5;
+pub const WAND_CHARGES: i32 = 4; // zaps in a fresh wand of lightning
+pub const PICKAXE_CHARGES: i32 = 5; // walls a pickaxe can dig through before it breaks
 pub const CONFUSE_RANGE: i32 = 8;
 pub const CONFUSE_NUM_TURNS: i32 = 10;
+pub const CONFUSE_BURST_RADIUS: i32 = 2;
+pub const CONFUSED_FLAVOR_CHANCE: u32 = 20; // percent of turns a confused monster in view gets a message
+pub const FREEZE_RANGE: i32 = 8;
+pub const FREEZE_NUM_TURNS: i32 = 5;
 pub const FIREBALL_RADIUS: i32 = 3;
 pub const FIREBALL_DAMAGE: i32 = 25;
+pub const FIREBALL_FLASH_LIGHT: f32 = 1.0; // how brightly the blast lights up the tiles around it, for a frame
+pub const LIGHT_ORB_RANGE: f32 = 8.0;
+pub const LIGHT_ORB_RADIUS: i32 = 4;
+pub const LIGHT_ORB_TURNS: i32 = 40;
+pub const ACID_DAMAGE: i32 = 15;
+pub const THROW_RANGE: f32 = 6.0;
+pub const SPLASH_RADIUS: i32 = 1;
+pub const FIREBALL_NOISE_RADIUS: i32 = 8; // sleeping monsters this close to the blast wake up
+pub const BERSERK_NUM_TURNS: i32 = 10;
+pub const BERSERK_POWER_BONUS: i32 = 3;
+pub const BERSERK_DEFENSE_PENALTY: i32 = 2;
+
+// monsters
+pub const MONSTER_SIGHT_RANGE_LIT: f32 = 10.0; // how far most monsters can see a player standing in the light, see make_monster
+pub const MONSTER_SIGHT_RANGE_DARK: f32 = 1.5; // and a player hiding in the dark: only from right next to them
+pub const THIEF_ESCAPE_DISTANCE: f32 = 15.0; // a fleeing thief this far away and out of sight escapes
+pub const WAKE_RADIUS: f32 = 3.0; // sleeping monsters wake when the player comes this close
+pub const SLEEP_CHANCE: u32 = 30; // percent chance that a monster is placed asleep
+pub const MONSTER_MEMORY_TURNS: i32 = 8; // how long a monster searches for a player it lost sight of
+pub const SNEAK_ATTACK_MULTIPLIER: f32 = 2.0; // damage from the player striking a monster that hasn't noticed them
+pub const SNEAK_ATTACK_XP_MULTIPLIER: f32 = 1.5; // experience for a kill made with a sneak attack
+pub const PACK_RANGE: f32 = 8.0; // how far apart pack mates can be and still hunt together
+pub const PATHFIND_MAX_NODES: i32 = 500; // how many tiles A* explores before giving up
+pub const RANGED_ATTACK_RANGE: f32 = 6.0;
+pub const SUMMON_COOLDOWN: i32 = 5; // turns a summoner waits between calling up minions
+pub const SUMMON_CAP: usize = 3; // summoned monsters alive on a level at once
+pub const SPIDER_POISON_TURNS: i32 = 3;
+pub const SPIDER_POISON_DAMAGE: i32 = 2;
+pub const VARIANT_SCALE_PER_LEVEL: f32 = 0.1; // how much stronger a variant like a dire troll is per dungeon level
+pub const FLEE_HP_FRACTION: f32 = 0.25; // monsters below this fraction of their max hp run away
 
 // leveling up
 pub const LEVEL_UP_BASE: i32 = 200;
@@ -56,5 +134,100 @@ pub const LEVEL_SCREEN_WIDTH: i32 = 40;
 // character screen
 pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
 
+// options screen
+pub const OPTIONS_SCREEN_WIDTH: i32 = 40;
+
+// minimap
+pub const MINIMAP_SCALE: i32 = 2; // map tiles across (and down) each cell of the minimap
+
+// water and lava pools
+pub const POOL_CHANCE: u32 = 15; // percent of rooms with a pool in them
+pub const LAVA_MIN_LEVEL: u32 = 4; // pools can be lava from this level down
+pub const LAVA_DAMAGE: i32 = 10;
+pub const LAVA_GLOW_RADIUS: i32 = 3;
+
+// traps
+pub const TRAP_CHANCE: u32 = 20; // percent of rooms with a trap in them
+pub const TRAP_DETECT_CHANCE: f32 = 3.0; // percent chance per turn to spot a trap, for each tile of sight radius
+pub const SPIKE_TRAP_DAMAGE: i32 = 8;
+pub const DART_POISON_TURNS: i32 = 5;
+pub const DART_POISON_DAMAGE: i32 = 1;
+
+// experience for exploring
+pub const EXPLORE_TILES_PER_XP: i32 = 20; // newly explored tiles per grant
+pub const EXPLORE_XP: i32 = 1;
+pub const DESCEND_XP: i32 = 10; // times the level reached, the first time down
+
+// cursed gear
+pub const CURSE_CHANCE: u32 = 15; // percent of gear found on the floor that is cursed
+
+// hunger, counted in turns
+pub const HUNGER_HUNGRY: i32 = 1000;
+pub const HUNGER_WEAK: i32 = 1500;
+pub const HUNGER_STARVING: i32 = 1800;
+pub const HUNGER_DARK_RATE: i32 = 2; // hiding in the dark is safer, but burns through food twice as fast
+pub const STARVATION_DAMAGE: i32 = 1;
+pub const STARVATION_INTERVAL: i32 = 4; // turns between bites of starvation damage
+pub const RATION_NOURISHMENT: i32 = 800;
+
+// bows and arrows
+pub const BOW_RANGE: f32 = 8.0;
+pub const ARROWS_PER_BUNDLE: i32 = 10;
+
+// gold and shops
+pub const GOLD_PER_XP: i32 = 5; // a monster drops up to 1 gold for every this much experience it's worth
+pub const CORPSE_DECAY_TURNS: i32 = 300; // turns before remains rot away, spilling what they held
+pub const SHOP_LEVEL_INTERVAL: u32 = 3; // a shopkeeper sets up on every third level
+pub const SHOP_STOCK: u32 = 5;
+pub const SHOP_SCREEN_WIDTH: i32 = 50;
+
+// animations
+pub const FLASH_FRAMES: u32 = 2;
+
+// save slots
+pub const SAVE_VERSION: u32 = 27; // bump whenever the saved data model changes
+pub const SAVE_SLOTS: usize = 3;
+pub const SLOT_SCREEN_WIDTH: i32 = 50;
+
+// confirmation prompts
+pub const CONFIRM_SCREEN_WIDTH: i32 = 30;
+
+// prologue, a hand-authored level played before dungeon level 1
+pub const PROLOGUE_FILE: &str = "prologue.txt";
+pub const PLAY_PROLOGUE_DEFAULT: bool = true;
+
+// touch sense: in the dark, the tiles right next to the player are always revealed
+pub const TOUCH_SENSE_DEFAULT: bool = true;
+
+// orthogonal movement: no diagonal steps, and distances are counted in steps
+pub const ORTHOGONAL_MOVEMENT_DEFAULT: bool = false;
+
+// carrying by weight: the player can only carry so much, depending on their strength
+pub const CARRY_BY_WEIGHT_DEFAULT: bool = false;
+pub const CARRY_WEIGHT_BASE: i32 = 20;
+pub const CARRY_WEIGHT_PER_POWER: i32 = 5; // per point of base power
+
+// permadeath: a run's save is deleted when the player dies
+pub const PERMADEATH_DEFAULT: bool = true;
+
+// light warning: the first step from the dark into a lit tile only warns, the next one goes
+pub const WARN_LIGHT_DEFAULT: bool = true;
+
+// shadowcasting: use the field of view in fov.rs rather than libtcod's
+pub const SHADOWCASTING_DEFAULT: bool = false;
+
+// the end of a run
+pub const FINAL_DUNGEON_LEVEL: u32 = 10; // the usual deepest level; going down its stairs wins the run
+pub const ENDLESS_SCALE_PER_LEVEL: f32 = 0.1; // how much tougher monsters get for each level past it
+
 // player will always be the first object
-pub const PLAYER: usize = 0;
\ No newline at end of file
+pub const PLAYER: usize = 0;
+
+// profiling, see `roguelike --profile`
+pub const PROFILE_TURNS: u32 = 1000;
+pub const PROFILE_SEED: u32 = 42;
+pub const PROFILE_TURNS_PER_LEVEL: u32 = 100; // generate a fresh level this often
+
+// difficulty, as a multiplier on monster numbers, stats and troll chance (and a divisor on healing potions)
+pub const DIFFICULTY_EASY: f32 = 0.75;
+pub const DIFFICULTY_HARD: f32 = 1.25;
@@ -0,0 +1,278 @@
+/// this file holds a field of view written in plain rust, so the game doesn't have to use libtcod's
+use crate::constants::*;
+use crate::user_defined::*;
+
+use tcod::map::{Map as FovMap}; // the 'Map as FovMap' section renames the tcod fov map
+                                // so that it doesn't conflict with our user defined Map
+
+// the four quadrants around the origin, as the direction (x, y) each one's rows step out in.
+// a row's columns run across it, so the tile at (depth, column) is offset by
+// x = depth * x + column * |y|, y = depth * y + column * |x|
+const QUADRANTS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+// a slope across a quadrant as a fraction (numerator, denominator), kept exact so
+// two tiles looking at each other always agree on where a shadow's edge falls
+type Slope = (i32, i32);
+
+// symmetric shadowcasting: each quadrant is scanned row by row outwards from the origin,
+// and a wall casts a shadow (a range of slopes) that hides everything behind it.
+// a floor tile is only seen when its center is in view, so if one floor tile can see
+// another, the other can see it back. like tcod with FOV_LIGHT_WALLS, the walls that
+// stop sight are themselves seen
+pub struct Shadowcast {
+    width: i32,
+    height: i32,
+    transparent: Vec<bool>,
+    visible: Vec<bool>,
+}
+
+impl Shadowcast {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        Shadowcast {
+            width: width,
+            height: height,
+            transparent: vec![false; size],
+            visible: vec![false; size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((x + y * self.width) as usize)
+        }
+    }
+
+    /// light up one quadrant from `depth` outwards, between the start and end slopes
+    fn scan(&mut self, origin: (i32, i32), (qx, qy): (i32, i32), depth: i32, start: Slope, end: Slope,
+        radius: i32) {
+        if depth > radius {
+            return;
+        }
+        // the columns whose centers are nearest the slopes, rounding into the range on a tie
+        let first = (2 * depth * start.0 + start.1).div_euclid(2 * start.1);
+        let last = -(-(2 * depth * end.0 - end.1)).div_euclid(2 * end.1);
+
+        let mut start = start;
+        let mut previous_transparent = None;
+        for column in first..last + 1 {
+            let x = origin.0 + depth * qx + column * qy.abs();
+            let y = origin.1 + depth * qy + column * qx.abs();
+            let index = self.index(x, y);
+            // off the map counts as a wall
+            let transparent = index.map_or(false, |index| self.transparent[index]);
+
+            let center_in_view = column * start.1 >= depth * start.0 && column * end.1 <= depth * end.0;
+            if (!transparent || center_in_view) && column * column + depth * depth <= radius * radius {
+                if let Some(index) = index {
+                    self.visible[index] = true;
+                }
+            }
+
+            // the slope through this tile's near edge, where a shadow starts or stops
+            let edge = (2 * column - 1, 2 * depth);
+            match (previous_transparent, transparent) {
+                // out of a wall's shadow, so carry on from where it ended
+                (Some(false), true) => start = edge,
+                // a wall starts a shadow: scan on past the open tiles before it
+                (Some(true), false) => self.scan(origin, (qx, qy), depth + 1, start, edge, radius),
+                _ => {}
+            }
+            previous_transparent = Some(transparent);
+        }
+        if previous_transparent == Some(true) {
+            self.scan(origin, (qx, qy), depth + 1, start, end, radius);
+        }
+    }
+}
+
+impl FieldOfView for Shadowcast {
+    fn compute(&mut self, x: i32, y: i32, radius: i32) {
+        for visible in self.visible.iter_mut() {
+            *visible = false;
+        }
+        let origin = match self.index(x, y) {
+            Some(index) => index,
+            None => return,
+        };
+        self.visible[origin] = true;
+        // like tcod, a radius of 0 means there's no limit
+        let radius = if radius > 0 { radius } else { self.width.max(self.height) };
+        for &quadrant in QUADRANTS.iter() {
+            self.scan((x, y), quadrant, 1, (-1, 1), (1, 1), radius);
+        }
+    }
+
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).map_or(false, |index| self.visible[index])
+    }
+
+    fn set(&mut self, x: i32, y: i32, transparent: bool, _walkable: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.transparent[index] = transparent;
+        }
+    }
+}
+
+/// an empty field of view the size of the map, of the kind picked in the options
+pub fn new_fov(settings: &Settings) -> Box<dyn FieldOfView> {
+    if settings.shadowcasting {
+        Box::new(Shadowcast::new(MAP_WIDTH, MAP_HEIGHT))
+    } else {
+        Box::new(FovMap::new(MAP_WIDTH, MAP_HEIGHT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a field of view for a small map drawn with '#' for walls and '.' for floor
+    fn shadowcast_from(rows: &[&str]) -> Shadowcast {
+        let mut fov = Shadowcast::new(rows[0].len() as i32, rows.len() as i32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                fov.set(x as i32, y as i32, c != '#', c != '#');
+            }
+        }
+        fov
+    }
+
+    const ROOM: &[&str] = &[
+        "#########",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#########",
+    ];
+
+    #[test]
+    fn sees_the_whole_open_room_and_its_walls() {
+        let mut fov = shadowcast_from(ROOM);
+        fov.compute(4, 4, 0);
+        for y in 0..9 {
+            for x in 0..9 {
+                assert!(fov.is_in_fov(x, y), "({}, {}) should be in view", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn radius_limits_how_far_it_sees() {
+        let mut fov = shadowcast_from(ROOM);
+        fov.compute(4, 4, 2);
+        assert!(fov.is_in_fov(4, 4));
+        assert!(fov.is_in_fov(6, 4));
+        assert!(fov.is_in_fov(4, 2));
+        assert!(!fov.is_in_fov(7, 4));
+        assert!(!fov.is_in_fov(1, 1));
+    }
+
+    const PILLAR: &[&str] = &[
+        "#########",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#..#....#",
+        "#.......#",
+        "#.......#",
+        "#.......#",
+        "#########",
+    ];
+
+    #[test]
+    fn a_pillar_casts_a_shadow_but_is_seen_itself() {
+        let mut fov = shadowcast_from(PILLAR);
+        fov.compute(1, 4, 0);
+        assert!(fov.is_in_fov(2, 4));
+        assert!(fov.is_in_fov(3, 4));
+        assert!(!fov.is_in_fov(4, 4));
+        assert!(!fov.is_in_fov(7, 4));
+        // off to the side of the shadow is still in view
+        assert!(fov.is_in_fov(7, 1));
+        assert!(fov.is_in_fov(7, 7));
+    }
+
+    // if one floor tile sees another, the other sees it back
+    fn assert_symmetric(rows: &[&str]) {
+        let floor: Vec<(i32, i32)> = (0..rows.len() as i32)
+            .flat_map(|y| (0..rows[0].len() as i32).map(move |x| (x, y)))
+            .filter(|&(x, y)| rows[y as usize].as_bytes()[x as usize] == b'.')
+            .collect();
+        let mut from_a = shadowcast_from(rows);
+        let mut from_b = shadowcast_from(rows);
+        for &a in &floor {
+            from_a.compute(a.0, a.1, 0);
+            for &b in &floor {
+                from_b.compute(b.0, b.1, 0);
+                assert_eq!(from_a.is_in_fov(b.0, b.1), from_b.is_in_fov(a.0, a.1),
+                    "{:?} and {:?} disagree about seeing each other", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn seeing_is_symmetric_around_a_pillar() {
+        assert_symmetric(PILLAR);
+    }
+
+    #[test]
+    fn seeing_is_symmetric_in_a_cluttered_room() {
+        assert_symmetric(&[
+            "##############",
+            "#....#.......#",
+            "#.##...#..#..#",
+            "#.....##.....#",
+            "#..#.......#.#",
+            "##.....#.....#",
+            "#...#....##..#",
+            "#.......#....#",
+            "##############",
+        ]);
+    }
+
+    #[test]
+    fn a_wall_hides_the_room_behind_it() {
+        let mut fov = shadowcast_from(&[
+            "#########",
+            "#...#...#",
+            "#...#...#",
+            "#...#...#",
+            "#########",
+        ]);
+        fov.compute(2, 2, 0);
+        assert!(fov.is_in_fov(4, 2));
+        for y in 1..4 {
+            for x in 5..8 {
+                assert!(!fov.is_in_fov(x, y), "({}, {}) should be hidden", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn a_new_compute_forgets_the_old_view() {
+        let mut fov = shadowcast_from(ROOM);
+        fov.compute(4, 4, 1);
+        assert!(fov.is_in_fov(5, 4));
+        fov.compute(1, 1, 1);
+        assert!(!fov.is_in_fov(5, 4));
+        assert!(!fov.is_in_fov(-1, 0));
+    }
+
+    #[test]
+    fn nothing_is_seen_from_off_the_map() {
+        let mut fov = shadowcast_from(ROOM);
+        fov.compute(-1, 4, 0);
+        for y in 0..9 {
+            for x in 0..9 {
+                assert!(!fov.is_in_fov(x, y));
+            }
+        }
+    }
+}
@@ -3,10 +3,50 @@ use crate::constants::*;
 use crate::user_defined::*;
 use crate::render::*;
 use crate::spells::*;
+use crate::ai::ai_take_turn;
 
 use tcod::colors::{self};
-use tcod::map::{Map as FovMap};
 use std::cmp;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// everything that happens in the world after the player has taken their turn. nothing here
+/// draws to the screen, so it runs the same in the game and without a window (see profile).
+/// animations are only queued up, for the caller to play if it has somewhere to play them
+pub fn simulate_turn(game: &mut Game, objects: &mut Vec<Object>, fov_map: &dyn FieldOfView) {
+    game.turn_count += 1;
+    collect_gold(objects, game);
+
+    // let monsters take their turn
+    for id in 0..objects.len() {
+        if objects[id].ai.is_some() {
+            ai_take_turn(id, game, objects, fov_map);
+        }
+    }
+    monsters_pick_up_items(objects, game, fov_map);
+
+    // status effects wear off and torches burn down as turns go by
+    if objects[PLAYER].alive {
+        tick_status_effects(objects, game, fov_map);
+        tick_hunger(objects, game);
+        detect_traps(game, objects, fov_map);
+        burn_emitters(objects, game, fov_map);
+        remove_expired(objects);
+        regenerate(objects, game, fov_map);
+    }
+
+    // update player fov_radius if necessary
+    // we do this after the monsters take their turn (for now); fov is recomputed in the render_all function
+    // this way the player can predict what the monster is going to do based on the fov when they take a turn
+    // instead of re-computing in between player and monster actions
+    if objects[PLAYER].alive {
+        // the lit flags were set at the last render, before anything moved; a carried torch moves with the player
+        update_lighting(objects, game);
+        dazzle(objects, game);
+        adjust_eyes(objects, game);
+    }
+}
 
 pub fn is_blocked(x: i32, y:i32, map: &Map, objects: &[Object]) -> bool {
     // first test the map tile
@@ -39,11 +79,111 @@ pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (
     }
 }
 
+/// true if nothing blocks sight on the straight line between two positions.
+/// walks the line with bresenham's algorithm; the end points themselves don't block
+pub fn line_of_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let step_x = if x < to.0 { 1 } else { -1 };
+    let step_y = if y < to.1 { 1 } else { -1 };
+    let mut error = dx + dy;
+    while (x, y) != to {
+        if (x, y) != from && map[x as usize][y as usize].block_sight {
+            return false;
+        }
+        let error2 = 2 * error;
+        if error2 >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if error2 <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    true
+}
+
 /// move by the given amount, if the destination is not blocked
 pub fn move_by(id: usize, dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
     let (x, y) = objects[id].pos();
     if !is_blocked(x + dx, y + dy, &game.map, objects){
+        // monsters won't wade into lava
+        if id != PLAYER && game.map[(x + dx) as usize][(y + dy) as usize].terrain == Terrain::Lava {
+            return;
+        }
         objects[id].set_pos(x + dx, y + dy);
+        // monsters know where the traps in their lair are, the player doesn't
+        if id == PLAYER {
+            spring_trap(game, objects);
+            enter_terrain(game, objects);
+        }
+    }
+}
+
+/// what happens to the player when they step into water or lava
+pub fn enter_terrain(game: &mut Game, objects: &mut [Object]) {
+    let (x, y) = objects[PLAYER].pos();
+    let player = &mut objects[PLAYER];
+    match game.map[x as usize][y as usize].terrain {
+        Terrain::Ground => {},
+        Terrain::Water => {
+            if player.emitter.is_some() {
+                player.emitter = None;
+                game.log.add("Your torch hisses out in the water.", colors::LIGHT_BLUE);
+            }
+        },
+        Terrain::Lava => {
+            game.log.add("The lava burns you!", colors::FLAME);
+            player.take_damage(LAVA_DAMAGE, game);
+        },
+    }
+}
+
+/// set off any trap the player is standing on
+pub fn spring_trap(game: &mut Game, objects: &mut [Object]) {
+    let trap_id = match objects.iter().position(|object| object.trap.is_some() && object.pos() == objects[PLAYER].pos()) {
+        Some(trap_id) => trap_id,
+        None => return,
+    };
+    let trap = objects[trap_id].trap.unwrap();
+    if !trap.detected {
+        reveal_trap(trap_id, objects);
+    }
+    game.log.add(format!("You step on a {}!", objects[trap_id].name), colors::ORANGE);
+    let player = &mut objects[PLAYER];
+    match trap.kind {
+        TrapKind::Spike => {
+            player.take_damage(SPIKE_TRAP_DAMAGE, game);
+        },
+        TrapKind::PoisonDart => {
+            let effect = StatusEffect{kind: StatusKind::Poison, turns_left: DART_POISON_TURNS, power: DART_POISON_DAMAGE};
+            player.status_effects.retain(|old| old.kind != effect.kind);
+            player.status_effects.push(effect);
+            game.log.add(format!("{} is {}!", player.name, effect.kind), colors::LIGHT_GREEN);
+        },
+    }
+}
+
+/// mark a trap as found, so it's drawn and can be walked around
+pub fn reveal_trap(trap_id: usize, objects: &mut [Object]) {
+    if let Some(trap) = objects[trap_id].trap.as_mut() {
+        trap.detected = true;
+    }
+    objects[trap_id].always_visible = true;
+}
+
+/// the player might notice hidden traps they can see. the better their eyes have
+/// adjusted (the bigger their sight radius), the better the chance
+pub fn detect_traps(game: &mut Game, objects: &mut [Object], fov_map: &dyn FieldOfView) {
+    let chance = objects[PLAYER].fov_radius * TRAP_DETECT_CHANCE;
+    for id in 0..objects.len() {
+        if objects[id].is_hidden() && fov_map.is_in_fov(objects[id].x, objects[id].y) &&
+            (rand::thread_rng().gen_range(0, 100) as f32) < chance {
+            reveal_trap(id, objects);
+            game.log.add(format!("You spot a {}.", objects[id].name), colors::LIGHT_CYAN);
+        }
     }
 }
 
@@ -56,12 +196,326 @@ pub fn move_towards(id: usize, target_x: i32, target_y: i32, game: &mut Game, ob
 
     // normalize it to length 1 (preserving direction), then round it and 
     // convert it to integer so the movement is restricted to the map grid
-    let dx = (dx as f32 / distance).round() as i32;
-    let dy = (dy as f32 / distance).round() as i32;
+    let (dx, dy) = if game.settings.orthogonal_movement {
+        // no diagonals, so step along whichever axis is further away
+        if dx.abs() >= dy.abs() { (dx.signum(), 0) } else { (0, dy.signum()) }
+    } else {
+        ((dx as f32 / distance).round() as i32, (dy as f32 / distance).round() as i32)
+    };
     move_by(id, dx, dy, game, objects);
 }
 
-pub fn toggle_equipment(inventory_id: usize, _objects: &mut [Object], game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+/// find a path from start to goal with A*, going around walls and blocking objects.
+/// the goal itself may be blocked (it's usually the player). the returned path
+/// is ordered from the goal back to the first step, so steps can be popped off the end.
+/// gives up after exploring max_nodes tiles
+pub fn find_path(start: (i32, i32), goal: (i32, i32), max_nodes: i32, game: &Game, objects: &[Object]) -> Option<Vec<(i32, i32)>> {
+    let diagonals = !game.settings.orthogonal_movement;
+    let heuristic = |(x, y): (i32, i32)| {
+        let (dx, dy) = ((x - goal.0).abs(), (y - goal.1).abs());
+        if diagonals { cmp::max(dx, dy) } else { dx + dy }
+    };
+
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut cost: HashMap<(i32, i32), i32> = HashMap::new();
+    open.push(Reverse((heuristic(start), start)));
+    cost.insert(start, 0);
+    let index = ObjectIndex::new(objects);
+
+    let mut expanded = 0;
+    while let Some(Reverse((_, current))) = open.pop() {
+        if current == goal {
+            // walk back from the goal to rebuild the path
+            let mut path = vec![];
+            let mut step = goal;
+            while step != start {
+                path.push(step);
+                step = came_from[&step];
+            }
+            return Some(path);
+        }
+
+        // give up on far away or unreachable goals rather than searching the whole map
+        expanded += 1;
+        if expanded > max_nodes {
+            break;
+        }
+
+        for &(dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            if !diagonals && dx != 0 && dy != 0 {
+                continue;
+            }
+            let next = (current.0 + dx, current.1 + dy);
+            if next.0 < 0 || next.1 < 0 || next.0 >= MAP_WIDTH || next.1 >= MAP_HEIGHT {
+                continue;
+            }
+            if next != goal && index.is_blocked(next.0, next.1, &game.map, objects) {
+                continue;
+            }
+            // go around lava and the traps the player knows about
+            if next != goal && (game.map[next.0 as usize][next.1 as usize].terrain == Terrain::Lava ||
+                index.at(next).iter().any(|&id| objects[id].trap.map_or(false, |trap| trap.detected))) {
+                continue;
+            }
+            let new_cost = cost[&current] + 1;
+            if cost.get(&next).map_or(true, |&old_cost| new_cost < old_cost) {
+                cost.insert(next, new_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((new_cost + heuristic(next), next)));
+            }
+        }
+    }
+    None
+}
+
+/// every tile on a straight line between two points, not including the start
+pub fn line_between(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    // bresenham's line algorithm
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dx + dy;
+    let (mut x, mut y) = from;
+    let mut line = vec![];
+    while (x, y) != to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        line.push((x, y));
+    }
+    line
+}
+
+/// breadth-first search from the player to the nearest unexplored floor tile
+/// they can reach, returning the first step of the way there
+pub fn step_towards_unexplored(game: &Game, objects: &[Object]) -> Option<(i32, i32)> {
+    let diagonals = !game.settings.orthogonal_movement;
+    let start = objects[PLAYER].pos();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+    came_from.insert(start, start);
+    let index = ObjectIndex::new(objects);
+
+    while let Some(current) = queue.pop_front() {
+        if !game.map[current.0 as usize][current.1 as usize].explored {
+            // walk back until the step right next to the start
+            let mut step = current;
+            while came_from[&step] != start {
+                step = came_from[&step];
+            }
+            return Some(step);
+        }
+        for &(dx, dy) in &[(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            if !diagonals && dx != 0 && dy != 0 {
+                continue;
+            }
+            let next = (current.0 + dx, current.1 + dy);
+            if next.0 < 0 || next.1 < 0 || next.0 >= MAP_WIDTH || next.1 >= MAP_HEIGHT {
+                continue;
+            }
+            if came_from.contains_key(&next) || index.is_blocked(next.0, next.1, &game.map, objects) ||
+                game.map[next.0 as usize][next.1 as usize].terrain == Terrain::Lava {
+                continue;
+            }
+            came_from.insert(next, current);
+            queue.push_back(next);
+        }
+    }
+    None
+}
+
+/// take one step along a path towards the goal, returning what's left of it.
+/// the path is recomputed when the goal has moved or the next step is blocked,
+/// and if there's no path at all the object just heads straight for the goal
+pub fn follow_path(id: usize, goal: (i32, i32), path: Vec<(i32, i32)>, game: &mut Game, objects: &mut [Object]) -> Vec<(i32, i32)> {
+    let mut path = path;
+    let (x, y) = objects[id].pos();
+    let next_step_ok = path.last().map_or(false, |&next| {
+        let adjacent = (next.0 - x).abs() <= 1 && (next.1 - y).abs() <= 1;
+        adjacent && (next == goal || !is_blocked(next.0, next.1, &game.map, objects))
+    });
+    if path.first() != Some(&goal) || !next_step_ok {
+        path = find_path(objects[id].pos(), goal, PATHFIND_MAX_NODES, game, objects).unwrap_or_default();
+    }
+
+    match path.pop() {
+        Some((next_x, next_y)) => move_by(id, next_x - x, next_y - y, game, objects),
+        None => move_towards(id, goal.0, goal.1, game, objects),
+    }
+    path
+}
+
+/// move an object away from a position, the inverse of move_towards. if the
+/// direct step is blocked, take any other step that still gains distance.
+/// returns false if there was nowhere to go (i.e. the object is cornered)
+pub fn move_away(id: usize, target_x: i32, target_y: i32, game: &mut Game, objects: &mut [Object]) -> bool {
+    let (x, y) = objects[id].pos();
+    let dx = x - target_x;
+    let dy = y - target_y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // the direct step away, same as move_towards but flipped
+    let mut steps = vec![((dx as f32 / distance).round() as i32, (dy as f32 / distance).round() as i32)];
+    for step_x in -1..2 {
+        for step_y in -1..2 {
+            steps.push((step_x, step_y));
+        }
+    }
+    let orthogonal = game.settings.orthogonal_movement;
+    let current_distance = game_distance(game, (x, y), (target_x, target_y));
+    let step = steps.into_iter().find(|&(step_x, step_y)| {
+        (step_x, step_y) != (0, 0) &&
+            !(orthogonal && step_x != 0 && step_y != 0) &&
+            !is_blocked(x + step_x, y + step_y, &game.map, objects) &&
+            game_distance(game, (x + step_x, y + step_y), (target_x, target_y)) > current_distance
+    });
+    match step {
+        Some((step_x, step_y)) => {
+            move_by(id, step_x, step_y, game, objects);
+            true
+        }
+        None => false,
+    }
+}
+
+/// distance between two positions as the rules count it: a straight line, or the
+/// number of orthogonal steps when diagonal movement is turned off
+pub fn game_distance(game: &Game, from: (i32, i32), to: (i32, i32)) -> f32 {
+    let dx = to.0 - from.0;
+    let dy = to.1 - from.1;
+    if game.settings.orthogonal_movement {
+        (dx.abs() + dy.abs()) as f32
+    } else {
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+}
+
+/// dead monsters drop whatever they were carrying on their tile
+pub fn drop_carried_items(objects: &mut Vec<Object>, game: &mut Game) {
+    let mut dropped = vec![];
+    for object in objects.iter_mut() {
+        // remains keep hold of their things until they're searched or rot away
+        if object.fighter.is_none() && !object.corpse && !object.inventory.is_empty() {
+            for mut item in object.inventory.drain(..) {
+                if item.gold > 0 {
+                    game.log.add(format!("{} gold coins spill from the {}.", item.gold, object.name), colors::GOLD);
+                } else {
+                    let name = item_name(&item, game);
+                    game.log.add(format!("A {} falls from the {}.", name, object.name), colors::YELLOW);
+                }
+                // a monster's gear comes off as it falls
+                if let Some(ref mut equipment) = item.equipment {
+                    equipment.equipped = false;
+                }
+                item.set_pos(object.x, object.y);
+                dropped.push(item);
+            }
+        }
+    }
+    objects.extend(dropped);
+}
+
+/// scavenging monsters pick up a potion or piece of gear they're standing on,
+/// wearing the gear if nothing is in that slot yet
+pub fn monsters_pick_up_items(objects: &mut Vec<Object>, game: &mut Game, fov_map: &dyn FieldOfView) {
+    // pair each scavenger with one item it wants from its tile, before anything is moved
+    let mut pickups: Vec<(usize, usize)> = vec![];
+    for (monster_id, monster) in objects.iter().enumerate() {
+        let awake = match monster.ai {
+            Some(Ai::Sleeping{..}) | Some(Ai::Frozen{..}) | Some(Ai::Escaped) | None => false,
+            _ => true,
+        };
+        if !monster.scavenger || !awake || monster.fighter.is_none() {
+            continue;
+        }
+        let wanted = (0..objects.len()).find(|&item_id| {
+            let item = &objects[item_id];
+            item.pos() == monster.pos() && item.gold == 0 &&
+                (item.item.map_or(false, Item::is_potion) || item.equipment.is_some()) &&
+                !pickups.iter().any(|&(_, taken)| taken == item_id)
+        });
+        if let Some(item_id) = wanted {
+            pickups.push((monster_id, item_id));
+        }
+    }
+
+    // take the items out of the level, highest id first so the others stay put
+    pickups.sort_by(|a, b| b.1.cmp(&a.1));
+    let mut taken = vec![];
+    for &(monster_id, item_id) in &pickups {
+        taken.push((monster_id, objects.remove(item_id)));
+    }
+    for (monster_id, mut item) in taken {
+        // the monster's id moved down once for every item removed from before it
+        let monster_id = monster_id - pickups.iter().filter(|&&(_, item_id)| item_id < monster_id).count();
+        let monster = &mut objects[monster_id];
+        let visible = fov_map.is_in_fov(monster.x, monster.y);
+        if let Some(ref mut equipment) = item.equipment {
+            let slot_free = !monster.inventory.iter()
+                .any(|other| other.equipment.map_or(false, |e| e.equipped && e.slot == equipment.slot));
+            equipment.equipped = slot_free;
+            if visible && slot_free {
+                game.log.add(format!("The {} picks up the {} and puts it on.", monster.name, item.name), colors::ORANGE);
+            }
+        }
+        if visible && item.equipment.map_or(true, |e| !e.equipped) {
+            game.log.add(format!("The {} picks up the {}.", monster.name, item_name(&item, game)), colors::ORANGE);
+        }
+        monster.inventory.push(item);
+    }
+}
+
+/// pick up any gold the player is standing on
+pub fn collect_gold(objects: &mut Vec<Object>, game: &mut Game) {
+    let player_pos = objects[PLAYER].pos();
+    let mut collected = 0;
+    objects.retain(|object| {
+        if object.gold > 0 && object.pos() == player_pos {
+            collected += object.gold;
+            false
+        } else {
+            true
+        }
+    });
+    if collected > 0 {
+        game.gold += collected;
+        game.log.add(format!("You pick up {} gold. You have {} now.", collected, game.gold), colors::GOLD);
+    }
+}
+
+/// count down temporary objects, removing the ones whose time is up.
+/// rotted remains leave what they held on the floor
+pub fn remove_expired(objects: &mut Vec<Object>) {
+    let mut spilled = vec![];
+    for object in objects.iter_mut() {
+        if let Some(ref mut lifetime) = object.lifetime {
+            *lifetime -= 1;
+            if *lifetime <= 0 && object.corpse {
+                let (x, y) = (object.x, object.y);
+                spilled.extend(object.inventory.drain(..).map(|mut item| {
+                    item.set_pos(x, y);
+                    item
+                }));
+            }
+        }
+    }
+    objects.extend(spilled);
+    objects.retain(|object| object.lifetime.map_or(true, |lifetime| lifetime > 0));
+}
+
+/// remove monsters that have escaped the level, along with anything they carried
+pub fn remove_escaped(objects: &mut Vec<Object>) {
+    objects.retain(|object| object.ai != Some(Ai::Escaped));
+}
+
+pub fn toggle_equipment(inventory_id: usize, _objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
     let equipment = match game.inventory[inventory_id].equipment {
         Some(equipment) => equipment,
         None => return UseResult::Cancelled,
@@ -75,14 +529,25 @@ pub fn toggle_equipment(inventory_id: usize, _objects: &mut [Object], game: &mut
 }
 
 /// find the closes enemy, up to a maximum range, an din the player's FOV
-pub fn closest_monster(max_range: i32, objects: &mut [Object], tcod: &Tcod) -> Option<usize> {
+pub fn closest_monster(max_range: i32, objects: &mut [Object], game: &Game, tcod: &Tcod) -> Option<usize> {
+    closest_target(PLAYER, max_range, objects, game, &tcod.fov)
+}
+
+/// find the closest fighter to a caster, up to a maximum range. the player picks
+/// from the monsters in their FOV, anyone else from whatever it has a line of sight to
+pub fn closest_target(caster_id: usize, max_range: i32, objects: &[Object], game: &Game, fov_map: &dyn FieldOfView) -> Option<usize> {
     let mut closest_enemy = None;
     let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) max range
+    let caster_pos = objects[caster_id].pos();
     for (id, object) in objects.iter().enumerate() {
-        if (id != PLAYER) && object.fighter.is_some() && object.ai.is_some() &&
-            tcod.fov.is_in_fov(object.x, object.y) {
-                // calculate the distance between the object and the player
-                let dist = objects[PLAYER].distance_to(object);
+        let in_sight = if caster_id == PLAYER {
+            object.ai.is_some() && fov_map.is_in_fov(object.x, object.y)
+        } else {
+            line_of_sight(&game.map, caster_pos, object.pos())
+        };
+        if (id != caster_id) && object.fighter.is_some() && in_sight {
+                // calculate the distance between the object and the caster
+                let dist = game_distance(game, caster_pos, object.pos());
                 if dist < closest_dist {
                     // it's closer, so remember it
                     closest_enemy = Some(id);
@@ -102,7 +567,17 @@ pub fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
     None
 }
 
-pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+/// the fighter standing where the player would move to, if any
+pub fn fighter_at(dx: i32, dy: i32, objects: &[Object]) -> Option<usize> {
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+    objects.iter().position(|object| object.fighter.is_some() && object.pos() == (x, y))
+}
+
+/// move the player, or attack whatever is in the way
+/// allies and neutrals are only attacked when `force` is set
+/// returns false if the player held back instead, after a warning about stepping into light
+pub fn player_move_or_attack(dx: i32, dy: i32, force: bool, game: &mut Game, objects: &mut [Object]) -> bool {
     // the coordinates the player is moving to/attacking
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
@@ -112,28 +587,225 @@ pub fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [O
         object.fighter.is_some() && object.pos() == (x, y)
     });
 
+    // a berserk player can't back away while there's an enemy next to them
+    let target_id = if target_id.is_none() && objects[PLAYER].has_status(StatusKind::Berserk) {
+        let adjacent_enemy = objects.iter().position(|object| {
+            object.faction == Faction::Hostile && object.fighter.is_some() &&
+                (object.x - objects[PLAYER].x).abs() <= 1 && (object.y - objects[PLAYER].y).abs() <= 1
+        });
+        if let Some(enemy_id) = adjacent_enemy {
+            game.log.add(format!("Your rage won't let you turn away from the {}!", objects[enemy_id].name),
+                colors::CRIMSON);
+        }
+        adjacent_enemy
+    } else {
+        target_id
+    };
+
     // attack if target found, move otherwise
     match target_id {
+        Some(target_id) if !force && objects[target_id].faction == Faction::Ally => {
+            // trade places with the ally instead of hitting it
+            let player_pos = objects[PLAYER].pos();
+            let ally_pos = objects[target_id].pos();
+            objects[PLAYER].set_pos(ally_pos.0, ally_pos.1);
+            objects[target_id].set_pos(player_pos.0, player_pos.1);
+            game.log.add(format!("You swap places with the {}.", objects[target_id].name), colors::WHITE);
+        }
+        Some(target_id) if !force && objects[target_id].faction == Faction::Neutral => {
+            game.log.add(format!("You bump into the {}.", objects[target_id].name), colors::WHITE);
+        }
         Some(target_id) => {
             let (player, target) = mut_two(PLAYER, target_id, objects);
             player.attack(target, game);
         }
         None => {
-            move_by(PLAYER, dx, dy, game, objects);
+            // the first step out of the dark into light only warns, since it costs the player their dark-adjusted eyes
+            if steps_into_light(x, y, game, objects) && game.light_warned != Some((x, y)) {
+                game.log.add("That tile is lit, and the light will undo your dark-adjusted eyes. Step again to go.", colors::LIGHT_YELLOW);
+                game.light_warned = Some((x, y));
+                return false;
+            }
+            game.light_warned = None;
+            walk_player(dx, dy, game, objects);
+        }
+    }
+    true
+}
+
+/// move the player one step, counting it in the run's stats by whether it was into the light or the dark.
+/// every way the player walks goes through here, whether by hand, running or travelling
+pub fn walk_player(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+    let from = objects[PLAYER].pos();
+    move_by(PLAYER, dx, dy, game, objects);
+    let (x, y) = objects[PLAYER].pos();
+    if (x, y) != from {
+        if game.map[x as usize][y as usize].lit {
+            game.stats.steps_lit += 1;
+        } else {
+            game.stats.steps_dark += 1;
+        }
+    }
+}
+
+/// whether moving the player to a tile would take them from the dark into light, when that's worth a warning
+pub fn steps_into_light(x: i32, y: i32, game: &Game, objects: &[Object]) -> bool {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    game.settings.warn_light && !is_blocked(x, y, &game.map, objects) &&
+        !game.map[player_x as usize][player_y as usize].lit && game.map[x as usize][y as usize].lit
+}
+
+/// the total weight of everything in the player's inventory
+pub fn carried_weight(game: &Game) -> i32 {
+    game.inventory.iter().map(|item| item.weight * item.count).sum()
+}
+
+/// how much weight the player can carry, based on their strength
+pub fn max_carry_weight(player: &Object) -> i32 {
+    CARRY_WEIGHT_BASE + player.fighter.map_or(0, |f| f.base_power) * CARRY_WEIGHT_PER_POWER
+}
+
+/// wake every sleeping monster within the radius of a noise
+pub fn wake_monsters(pos: (i32, i32), radius: f32, objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) {
+    for object in objects.iter_mut() {
+        if game_distance(game, object.pos(), pos) <= radius && object.wake_up()
+            && fov_map.is_in_fov(object.x, object.y) {
+            game.log.add(format!("The {} wakes up!", object.name), colors::LIGHT_BLUE);
+        }
+    }
+}
+
+/// count down every status effect by a turn, removing the ones that wore off
+pub fn tick_status_effects(objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) {
+    for (id, object) in objects.iter_mut().enumerate() {
+        // damage over time bites before the effect counts down
+        let poison: i32 = object.status_effects.iter()
+            .filter(|effect| effect.kind == StatusKind::Poison)
+            .map(|effect| effect.power)
+            .sum();
+        if poison > 0 && object.fighter.is_some() {
+            if id == PLAYER {
+                game.log.add(format!("The poison burns you for {} hit points.", poison), colors::LIGHT_GREEN);
+            }
+            object.take_damage(poison, game);
+        }
+
+        for effect in object.status_effects.iter_mut() {
+            effect.turns_left -= 1;
+        }
+        let (expired, active): (Vec<StatusEffect>, Vec<StatusEffect>) = object.status_effects.iter()
+            .partition(|effect| effect.turns_left <= 0);
+        for effect in expired {
+            if id == PLAYER {
+                game.log.add(format!("You are no longer {}.", effect.kind), colors::LIGHT_GREY);
+            } else if fov_map.is_in_fov(object.x, object.y) {
+                game.log.add(format!("The {} is no longer {}.", object.name, effect.kind), colors::LIGHT_GREY);
+            }
+        }
+        object.status_effects = active;
+    }
+}
+
+/// the player gets hungrier every turn, faster when hiding in the dark. past
+/// the thresholds they're warned, and once starving they waste away
+pub fn tick_hunger(objects: &mut [Object], game: &mut Game) {
+    let (x, y) = objects[PLAYER].pos();
+    let before = game.hunger;
+    game.hunger += if game.map[x as usize][y as usize].lit { 1 } else { HUNGER_DARK_RATE };
+
+    let crossed = |threshold| before < threshold && game.hunger >= threshold;
+    if crossed(HUNGER_STARVING) {
+        game.log.add("You are starving!", colors::RED);
+    } else if crossed(HUNGER_WEAK) {
+        game.log.add("You feel weak with hunger.", colors::ORANGE);
+    } else if crossed(HUNGER_HUNGRY) {
+        game.log.add("You are getting hungry.", colors::YELLOW);
+    }
+
+    if game.hunger >= HUNGER_STARVING && game.hunger % STARVATION_INTERVAL == 0 {
+        objects[PLAYER].take_damage(STARVATION_DAMAGE, game);
+    }
+}
+
+/// how hungry the player is, for the panel. None while they're fed
+pub fn hunger_status(game: &Game) -> Option<&'static str> {
+    match game.hunger {
+        h if h >= HUNGER_STARVING => Some("Starving"),
+        h if h >= HUNGER_WEAK => Some("Weak"),
+        h if h >= HUNGER_HUNGRY => Some("Hungry"),
+        _ => None,
+    }
+}
+
+/// whether any monster is in the player's fov
+pub fn monster_in_view(objects: &[Object], fov_map: &dyn FieldOfView) -> bool {
+    objects.iter().any(|object| {
+        object.fighter.is_some() && object.ai.is_some() && fov_map.is_in_fov(object.x, object.y)
+    })
+}
+
+/// the player slowly heals on their own, but not while a monster is in view
+pub fn regenerate(objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) {
+    if monster_in_view(objects, fov_map) {
+        return;
+    }
+    game.regen_progress += objects[PLAYER].regen_rate(game);
+    if game.regen_progress >= REGEN_THRESHOLD {
+        game.regen_progress -= REGEN_THRESHOLD;
+        objects[PLAYER].heal(1, game);
+    }
+}
+
+/// burn a turn of fuel from every emitter, putting out the ones that run dry
+pub fn burn_emitters(objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) {
+    for (id, object) in objects.iter_mut().enumerate() {
+        let burnt_out = match object.emitter.as_mut() {
+            Some(emitter) => {
+                emitter.fuel -= 1;
+                emitter.fuel <= 0
+            },
+            None => false,
+        };
+        if burnt_out {
+            object.emitter = None;
+            if id == PLAYER {
+                game.log.add("Your torch sputters and goes out.", colors::DARK_ORANGE);
+            } else if object.lifetime.is_none() {
+                // leave a charred stub behind
+                object.char = '-';
+                object.color = colors::DARKER_SEPIA;
+                object.name = "burnt-out torch".into();
+                if fov_map.is_in_fov(object.x, object.y) {
+                    game.log.add("A torch gutters and dies.", colors::DARK_ORANGE);
+                }
+            }
         }
     }
 }
 
 /// add to the player's inventory and remove from the map
 pub fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
-    if game.inventory.len() >= 26 {
-        game.log.add(format!("Your inventory is full, cannot pick up {}.", objects[object_id].name), colors::RED);
+    // arrows go straight into the quiver
+    if objects[object_id].item == Some(Item::Arrows) {
+        objects.swap_remove(object_id);
+        game.arrows += ARROWS_PER_BUNDLE;
+        game.log.add(format!("You pick up some arrows. You have {} now.", game.arrows), colors::GREEN);
+        return;
+    }
+    let too_heavy = game.settings.carry_by_weight &&
+        carried_weight(game) + objects[object_id].weight * objects[object_id].count > max_carry_weight(&objects[PLAYER]);
+    let stacks = stack_index(&objects[object_id], &game.inventory).is_some();
+    if game.inventory.len() >= 26 && !stacks {
+        game.log.add(format!("Your inventory is full, cannot pick up {}.", item_name(&objects[object_id], game)), colors::RED);
+    } else if too_heavy {
+        game.log.add(format!("The {} is too heavy, you can only carry {} more weight.",
+            objects[object_id].name, max_carry_weight(&objects[PLAYER]) - carried_weight(game)), colors::RED);
     } else {
         let item = objects.swap_remove(object_id);
-        game.log.add(format!("You picked up a {}!", item.name), colors::GREEN);
-        let index = game.inventory.len();
+        game.log.add(format!("You picked up a {}!", item_name(&item, game)), colors::GREEN);
+        note_item(item.item, game);
         let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
+        let index = add_to_inventory(item, game);
 
         // automatically equip, if the corresponding equipment slot is unused
         if let Some(slot) = slot {
@@ -144,45 +816,241 @@ pub fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game
     }
 }
 
-pub fn use_item(inventory_id: usize, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
+/// take everything out of a monster's remains: gold straight into the purse,
+/// items picked up one by one, with any that don't fit left on the floor
+pub fn search_corpse(corpse_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
+    let (x, y) = objects[corpse_id].pos();
+    let contents: Vec<Object> = objects[corpse_id].inventory.drain(..).collect();
+    game.log.add(format!("You search the {}.", objects[corpse_id].name), colors::LIGHT_GREY);
+    for mut item in contents {
+        if item.gold > 0 {
+            game.gold += item.gold;
+            game.log.add(format!("You pick up {} gold. You have {} now.", item.gold, game.gold), colors::GOLD);
+            continue;
+        }
+        // a monster's gear comes off as it's taken
+        if let Some(ref mut equipment) = item.equipment {
+            equipment.equipped = false;
+        }
+        item.set_pos(x, y);
+        objects.push(item);
+        let item_id = objects.len() - 1;
+        pick_item_up(item_id, objects, game);
+    }
+}
+
+/// add the monsters and items in view to the bestiary, the first time they're seen
+pub fn note_seen(objects: &[Object], game: &mut Game, fov_map: &dyn FieldOfView) {
+    for object in objects.iter().filter(|object| fov_map.is_in_fov(object.x, object.y) && !object.is_hidden()) {
+        if object.faction == Faction::Hostile && object.fighter.is_some() && !game.seen_monsters.contains(&object.name) {
+            game.seen_monsters.push(object.name.clone());
+        }
+        note_item(object.item, game);
+    }
+}
+
+fn note_item(item: Option<Item>, game: &mut Game) {
+    if let Some(item) = item {
+        if !game.seen_items.contains(&item) {
+            game.seen_items.push(item);
+        }
+    }
+}
+
+/// the name the player knows an item by: scrolls they haven't identified yet
+/// only show their label
+pub fn item_name(item: &Object, game: &Game) -> String {
+    match item.item {
+        Some(kind) if kind.is_scroll() && !game.identified.contains(&kind) => {
+            match game.scroll_labels.iter().find(|&&(labelled, _)| labelled == kind) {
+                Some(&(_, ref label)) => format!("scroll labeled {}", label),
+                None => "unlabeled scroll".into(),
+            }
+        },
+        _ => item.name.clone(),
+    }
+}
+
+/// learn what a kind of scroll is, for the rest of the game. returns false if it was already known
+pub fn identify(kind: Item, game: &mut Game) -> bool {
+    if game.identified.contains(&kind) {
+        return false;
+    }
+    game.identified.push(kind);
+    true
+}
+
+/// give every kind of scroll a random nonsense label, for a new game
+pub fn make_scroll_labels() -> Vec<(Item, String)> {
+    const SYLLABLES: &[&str] = &["ZEL", "GO", "MER", "FOO", "BAR", "XIX", "KLAA", "NUR", "ELAM",
+        "EBOW", "VE", "RE", "THA", "DAI", "YEN", "PRA", "TYR", "OX"];
+    let scrolls = [Item::Lightning, Item::Confuse, Item::Fireball, Item::Mapping, Item::Light,
+        Item::Freeze, Item::ConfuseBurst, Item::Identify, Item::RemoveCurse];
+    let mut rng = rand::thread_rng();
+    let mut labels: Vec<(Item, String)> = vec![];
+    for &scroll in &scrolls {
+        // two words of two syllables each, different from every other label
+        loop {
+            let mut syllable = || SYLLABLES[rng.gen_range(0, SYLLABLES.len())];
+            let label = format!("{}{} {}{}", syllable(), syllable(), syllable(), syllable());
+            if !labels.iter().any(|&(_, ref other)| *other == label) {
+                labels.push((scroll, label));
+                break;
+            }
+        }
+    }
+    labels
+}
+
+/// the inventory stack an item would join: one of the same consumable, by kind
+/// and name. equipment never stacks
+pub fn stack_index(item: &Object, inventory: &[Object]) -> Option<usize> {
+    if item.item.is_none() || item.equipment.is_some() || item.charges.is_some() {
+        return None;
+    }
+    inventory.iter().position(|other| other.item == item.item && other.name == item.name && other.equipment.is_none())
+}
+
+/// put an item in the inventory, on top of its stack if there is one. returns where it went
+pub fn add_to_inventory(item: Object, game: &mut Game) -> usize {
+    match stack_index(&item, &game.inventory) {
+        Some(index) => {
+            game.inventory[index].count += item.count;
+            index
+        },
+        None => {
+            game.inventory.push(item);
+            game.inventory.len() - 1
+        },
+    }
+}
+
+/// take a single item out of the inventory, splitting it off its stack if there's more than one
+pub fn take_from_inventory(inventory_id: usize, game: &mut Game) -> Object {
+    let stack = &mut game.inventory[inventory_id];
+    if stack.count > 1 {
+        stack.count -= 1;
+        let mut item = stack.clone();
+        item.count = 1;
+        item
+    } else {
+        game.inventory.remove(inventory_id)
+    }
+}
+
+pub fn use_item(inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
     use Item::*;
     // just call the 'use_function' if it's defined
     if let Some(item) = game.inventory[inventory_id].item {
-        let on_use: fn(usize, &mut [Object], &mut Game, &mut Tcod) -> UseResult = match item {
+        let on_use: fn(usize, &mut Vec<Object>, &mut Game, &mut Tcod) -> UseResult = match item {
             Heal => cast_heal,
             Lightning => cast_lightning,
             Confuse => cast_confuse,
             Fireball => cast_fireball,
+            Berserk => cast_berserk,
+            Torch => light_torch,
+            Mapping => cast_mapping,
+            Light => cast_light,
+            Freeze => cast_freeze,
+            ConfuseBurst => cast_confuse_burst,
+            Acid => cast_acid,
+            RegenRing => toggle_equipment,
             Sword => toggle_equipment,
             Shield => toggle_equipment,
+            Bow => toggle_equipment,
+            Helmet => toggle_equipment,
+            Armor => toggle_equipment,
+            Amulet => toggle_equipment,
+            Identify => cast_identify,
+            RemoveCurse => cast_remove_curse,
+            Ration => eat_ration,
+            Arrows => fill_quiver,
+            WandLightning => zap_lightning,
+            Dig => dig,
         };
-        match on_use(inventory_id, objects, game, tcod) {
+        let result = on_use(inventory_id, objects, game, tcod);
+        // reading a scroll tells the player what it is, unless they put it away unread
+        if result != UseResult::Cancelled && item.is_scroll() && identify(item, game) {
+            game.log.add(format!("It's a {}!", game.inventory[inventory_id].name), colors::LIGHT_CYAN);
+        }
+        match result {
             UseResult::UsedUp => {
                 // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
+                take_from_inventory(inventory_id, game);
+                game.stats.items_used += 1;
+            }
+            UseResult::UsedAndKept => game.stats.items_used += 1,
+            UseResult::UsedCharge => {
+                game.stats.items_used += 1;
+                let charges = game.inventory[inventory_id].charges.map_or(0, |charges| charges - 1);
+                game.inventory[inventory_id].charges = Some(charges);
+                if charges <= 0 {
+                    let wand = take_from_inventory(inventory_id, game);
+                    let worn_out = if item == Dig { "breaks" } else { "crumbles to dust" };
+                    game.log.add(format!("The {} {}.", wand.name, worn_out), colors::LIGHT_GREY);
+                }
             }
-            UseResult::UsedAndKept => {}, // do nothing
             UseResult::Cancelled => {
                 game.log.add("Cancelled", colors::WHITE);
             }
         }
+        result
     } else {
         game.log.add(format!("The {} cannot be used.", game.inventory[inventory_id].name), colors::WHITE);
+        UseResult::Cancelled
+    }
+}
+
+/// throw an item from the inventory at a tile. potions shatter and splash
+/// everything around the tile, anything else just lands there
+pub fn throw_item(inventory_id: usize, target: (i32, i32), objects: &mut Vec<Object>, game: &mut Game) {
+    if game.inventory[inventory_id].is_stuck() {
+        game.log.add(format!("You can't let go of the {}, it's cursed!", game.inventory[inventory_id].name), colors::RED);
+        return;
+    }
+    let mut item = take_from_inventory(inventory_id, game);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.log);
+    }
+    match item.item {
+        Some(potion) if potion.is_potion() => {
+            game.log.add(format!("The {} shatters!", item.name), colors::YELLOW);
+            splash_potion(potion, target, SPLASH_RADIUS, PLAYER, objects, game);
+        },
+        _ => {
+            game.log.add(format!("You throw the {}.", item_name(&item, game)), colors::YELLOW);
+            item.set_pos(target.0, target.1);
+            objects.push(item);
+        },
     }
 }
 
 pub fn drop_item(inventory_id: usize,
             game: &mut Game,
             objects: &mut Vec<Object>) {
-    let mut item = game.inventory.remove(inventory_id);
+    if game.inventory[inventory_id].is_stuck() {
+        game.log.add(format!("You can't let go of the {}, it's cursed!", game.inventory[inventory_id].name), colors::RED);
+        return;
+    }
+    let mut item = take_from_inventory(inventory_id, game);
     if item.equipment.is_some() {
         item.dequip(&mut game.log);
     }
     item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
-    game.log.add(format!("You dropped a {}.", item.name), colors::YELLOW);
+    game.log.add(format!("You dropped a {}.", item_name(&item, game)), colors::YELLOW);
     objects.push(item);
 }
 
+/// turn tiles explored since the last turn into experience. each tile only
+/// counts the first time it's explored, so nothing is earned twice
+pub fn grant_exploration_xp(objects: &mut [Object], game: &mut Game) {
+    let xp = game.explore_progress / EXPLORE_TILES_PER_XP * EXPLORE_XP;
+    game.explore_progress %= EXPLORE_TILES_PER_XP;
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.xp += xp;
+    }
+}
+
 pub fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
     let player = &mut objects[PLAYER];
     let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
@@ -222,16 +1090,583 @@ pub fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
     }
 }
 
+/// the player's eyes adjust to the tile they're standing on: the torch radius
+/// contracts over a few turns in the light and slowly grows back in the dark
+pub fn adjust_eyes(objects: &mut [Object], game: &Game) {
+    let player = &mut objects[PLAYER];
+    let lit = game.map[player.x as usize][player.y as usize].lit;
+    player.fov_radius = adjusted_radius(player.fov_radius, lit);
+}
+
+/// stepping into the light with eyes wide open from the dark blinds the player for a moment
+pub fn dazzle(objects: &mut [Object], game: &mut Game) {
+    let player = &mut objects[PLAYER];
+    let lit = game.map[player.x as usize][player.y as usize].lit;
+    if lit && player.fov_radius >= DAZZLE_THRESHOLD && !player.has_status(StatusKind::Dazzled) {
+        player.status_effects.push(StatusEffect{kind: StatusKind::Dazzled, turns_left: DAZZLE_TURNS, power: 0});
+        game.log.add("The light blinds you!", colors::WHITE);
+    }
+}
+
+/// step the eye radius one turn towards its target for the light level
+pub fn adjusted_radius(radius: f32, lit: bool) -> f32 {
+    if lit {
+        let target = TORCH_RADIUS_IN_LIT_AREA as f32;
+        if radius > target { f32::max(target, radius - EYE_CONTRACT_RATE) } else { target }
+    } else { // player is in dark area
+        f32::min(TORCH_RADIUS_IN_DARK_AREA as f32, radius + EYE_DILATE_RATE)
+    }
+}
+
 /// initializes an FOV map based on the MAP_HEIGHT and MAP_WIDTH, using game.map
-pub fn create_fov_map(game: &Game) -> FovMap {
-    let mut fov_map = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+pub fn create_fov_map(game: &Game) -> Box<dyn FieldOfView> {
+    let mut fov_map = crate::fov::new_fov(&game.settings);
+    fill_fov(&mut *fov_map, &game.map);
+    fov_map
+}
+
+/// tell a field of view which tiles of the map block sight and movement
+pub fn fill_fov(fov: &mut dyn FieldOfView, map: &Map) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
-            fov_map.set(x, y, 
-                !game.map[x as usize][y as usize].block_sight,
-                !game.map[x as usize][y as usize].blocked);
+            fov.set(x, y,
+                !map[x as usize][y as usize].block_sight,
+                !map[x as usize][y as usize].blocked);
         }
     }
-    fov_map
 }
 
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // a game on a map of solid wall with the given floor carved out, drawn with
+    // '#' for walls, '.' for floor and '~' for lava, starting in the top left corner
+    pub(crate) fn game_with_map(rows: &[&str]) -> Game {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                match c {
+                    '.' => map[x][y] = Tile::empty(),
+                    '~' => {
+                        map[x][y] = Tile::empty();
+                        map[x][y].terrain = Terrain::Lava;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Game::new(map, Settings::new(), 0, Difficulty::Normal, None)
+    }
+
+    const HALL: &[&str] = &[
+        "#######",
+        "#.....#",
+        "#.....#",
+        "#.....#",
+        "#######",
+    ];
+
+    #[test]
+    fn path_runs_from_the_goal_back_to_the_next_step() {
+        let game = game_with_map(HALL);
+        let path = find_path((1, 2), (5, 2), 100, &game, &[]).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[0], (5, 2));
+        // a diagonal first step is just as short, so only the column is certain
+        assert_eq!(path.last().unwrap().0, 2);
+        assert!(!path.contains(&(1, 2)));
+    }
+
+    #[test]
+    fn path_goes_around_a_wall() {
+        let game = game_with_map(&[
+            "#######",
+            "#..#..#",
+            "#..#..#",
+            "#.....#",
+            "#######",
+        ]);
+        let path = find_path((1, 1), (5, 1), 100, &game, &[]).unwrap();
+        assert!(path.iter().all(|&(x, y)| !game.map[x as usize][y as usize].blocked));
+        assert!(path.contains(&(3, 3)));
+    }
+
+    #[test]
+    fn path_goes_around_lava() {
+        let game = game_with_map(&[
+            "#######",
+            "#..~..#",
+            "#..~..#",
+            "#.....#",
+            "#######",
+        ]);
+        let path = find_path((1, 1), (5, 1), 100, &game, &[]).unwrap();
+        assert!(path.iter().all(|&(x, y)| game.map[x as usize][y as usize].terrain != Terrain::Lava));
+    }
+
+    #[test]
+    fn path_goes_around_a_monster_but_can_end_on_one() {
+        let game = game_with_map(HALL);
+        let orc = Object::new(3, 2, 'o', "orc", colors::DESATURATED_GREEN, true);
+        let path = find_path((1, 2), (5, 2), 100, &game, std::slice::from_ref(&orc)).unwrap();
+        assert!(!path.contains(&(3, 2)));
+
+        let path = find_path((1, 2), (3, 2), 100, &game, &[orc]).unwrap();
+        assert_eq!(path[0], (3, 2));
+    }
+
+    #[test]
+    fn no_path_to_a_walled_off_goal() {
+        let game = game_with_map(&[
+            "#######",
+            "#..#..#",
+            "#..#..#",
+            "#..#..#",
+            "#######",
+        ]);
+        assert_eq!(find_path((1, 1), (5, 1), 1000, &game, &[]), None);
+    }
+
+    #[test]
+    fn path_search_gives_up_after_max_nodes() {
+        let game = game_with_map(HALL);
+        assert_eq!(find_path((1, 1), (5, 3), 2, &game, &[]), None);
+    }
+
+    #[test]
+    fn orthogonal_movement_never_steps_diagonally() {
+        let mut game = game_with_map(HALL);
+        game.settings.orthogonal_movement = true;
+        let path = find_path((1, 1), (5, 3), 100, &game, &[]).unwrap();
+        assert_eq!(path.len(), 6);
+        let mut steps = path.clone();
+        steps.push((1, 1));
+        for pair in steps.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!((a.0 - b.0).abs() + (a.1 - b.1).abs(), 1);
+        }
+    }
+
+    // what the player at objects[PLAYER] can see of the game's map
+    pub(crate) fn player_view(game: &Game, objects: &[Object]) -> crate::fov::Shadowcast {
+        let mut fov = crate::fov::Shadowcast::new(MAP_WIDTH, MAP_HEIGHT);
+        fill_fov(&mut fov, &game.map);
+        fov.compute(objects[PLAYER].x, objects[PLAYER].y, 0);
+        fov
+    }
+
+    fn poisoned_orc(x: i32, y: i32, hp: i32) -> Object {
+        let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
+        orc.alive = true;
+        orc.fighter = Some(Fighter{base_max_hp: 20, hp, base_defense: 0, base_power: 4,
+            on_death: DeathCallback::Monster, xp: 35});
+        orc.status_effects.push(StatusEffect{kind: StatusKind::Poison, turns_left: 3, power: 2});
+        orc
+    }
+
+    #[test]
+    fn poison_bites_every_turn_until_it_wears_off() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), poisoned_orc(3, 2, 20)];
+        objects[PLAYER].set_pos(1, 2);
+        let fov = player_view(&game, &objects);
+        for _ in 0..3 {
+            tick_status_effects(&mut objects, &mut game, &fov);
+        }
+        assert_eq!(objects[1].fighter.unwrap().hp, 14);
+        assert!(objects[1].status_effects.is_empty());
+        assert!(game.log.iter().any(|(message, _)| message == "The orc is no longer poisoned."));
+
+        // and once it's gone, it does no more harm
+        tick_status_effects(&mut objects, &mut game, &fov);
+        assert_eq!(objects[1].fighter.unwrap().hp, 14);
+    }
+
+    #[test]
+    fn poison_can_kill() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), poisoned_orc(3, 2, 3)];
+        objects[PLAYER].set_pos(1, 2);
+        let fov = player_view(&game, &objects);
+        tick_status_effects(&mut objects, &mut game, &fov);
+        assert!(objects[1].alive);
+        tick_status_effects(&mut objects, &mut game, &fov);
+        // the monster's death left its remains behind, and counts as a kill
+        assert!(!objects[1].alive);
+        assert!(objects[1].corpse);
+        assert_eq!(objects[1].name, "remains of orc");
+        assert_eq!(game.stats.kills.get("orc"), Some(&1));
+    }
+
+    #[test]
+    fn a_status_wearing_off_out_of_sight_isnt_logged() {
+        let mut game = game_with_map(&[
+            "#######",
+            "#..#..#",
+            "#..#..#",
+            "#######",
+        ]);
+        let mut objects = vec![crate::new_player(), poisoned_orc(5, 1, 20)];
+        objects[PLAYER].set_pos(1, 1);
+        let fov = player_view(&game, &objects);
+        for _ in 0..3 {
+            tick_status_effects(&mut objects, &mut game, &fov);
+        }
+        assert!(objects[1].status_effects.is_empty());
+        assert!(game.log.is_empty());
+    }
+
+    #[test]
+    fn line_of_sight_across_an_open_room() {
+        let game = game_with_map(HALL);
+        assert!(line_of_sight(&game.map, (1, 1), (5, 3)));
+        assert!(line_of_sight(&game.map, (5, 3), (1, 1)));
+        assert!(line_of_sight(&game.map, (2, 2), (2, 2)));
+    }
+
+    #[test]
+    fn a_wall_on_the_line_blocks_sight() {
+        let game = game_with_map(&[
+            "#########",
+            "#.......#",
+            "#...#...#",
+            "#.......#",
+            "#########",
+        ]);
+        // the line from (1, 1) to (7, 3) runs through (4, 2)
+        assert!(!line_of_sight(&game.map, (1, 1), (7, 3)));
+        assert!(!line_of_sight(&game.map, (1, 2), (7, 2)));
+        // but a wall at either end doesn't block seeing it
+        assert!(line_of_sight(&game.map, (1, 2), (4, 2)));
+        assert!(line_of_sight(&game.map, (4, 2), (1, 2)));
+    }
+
+    #[test]
+    fn a_line_grazing_a_corner_gets_past() {
+        let game = game_with_map(&[
+            "#########",
+            "#..#....#",
+            "#.#.....#",
+            "#.......#",
+            "#########",
+        ]);
+        // the line from (1, 1) to (7, 3) passes (2, 1) and (3, 2), brushing the corner of (3, 1)
+        assert!(line_of_sight(&game.map, (1, 1), (7, 3)));
+        // a diagonal through the corner wall itself is blocked
+        assert!(!line_of_sight(&game.map, (1, 1), (3, 3)));
+    }
+
+    #[test]
+    fn a_diagonal_squeezes_between_walls_touching_at_the_corners() {
+        let game = game_with_map(&[
+            "#####",
+            "#.#.#",
+            "##..#",
+            "#...#",
+            "#####",
+        ]);
+        // (2, 1) and (1, 2) are walls, but the line steps straight from (1, 1) to (2, 2)
+        assert!(line_of_sight(&game.map, (1, 1), (3, 3)));
+        assert!(line_of_sight(&game.map, (3, 3), (1, 1)));
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 0.001, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn eyes_contract_over_three_turns_in_the_light() {
+        let dark = TORCH_RADIUS_IN_DARK_AREA as f32;
+        let lit = TORCH_RADIUS_IN_LIT_AREA as f32;
+        let mut radius = dark;
+        let mut sight = vec![];
+        for turn in 1..4 {
+            radius = adjusted_radius(radius, true);
+            assert_close(radius, dark - (dark - lit) * turn as f32 / 3.0);
+            sight.push(radius.round() as i32);
+        }
+        // in between, the rounded radius the fov uses is somewhere between the two
+        assert_eq!(sight, vec![8, 5, 3]);
+        // and it settles there
+        assert_close(adjusted_radius(radius, true), lit);
+    }
+
+    #[test]
+    fn eyes_open_up_slowly_in_the_dark() {
+        let mut radius = TORCH_RADIUS_IN_LIT_AREA as f32;
+        for turn in 1..4 {
+            radius = adjusted_radius(radius, false);
+            assert_close(radius, TORCH_RADIUS_IN_LIT_AREA as f32 + EYE_DILATE_RATE * turn as f32);
+        }
+        // opening all the way takes far longer than closing
+        let mut turns = 3;
+        while radius < TORCH_RADIUS_IN_DARK_AREA as f32 {
+            radius = adjusted_radius(radius, false);
+            turns += 1;
+        }
+        assert_eq!(turns, 7);
+        assert_close(adjusted_radius(radius, false), TORCH_RADIUS_IN_DARK_AREA as f32);
+    }
+
+    #[test]
+    fn adjust_eyes_follows_the_light_on_the_players_tile() {
+        let mut game = game_with_map(HALL);
+        game.map[2][2].lit = true;
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].fov_radius = TORCH_RADIUS_IN_DARK_AREA as f32;
+        objects[PLAYER].set_pos(2, 2);
+        adjust_eyes(&mut objects, &game);
+        assert_close(objects[PLAYER].fov_radius, TORCH_RADIUS_IN_DARK_AREA as f32 - EYE_CONTRACT_RATE);
+        objects[PLAYER].set_pos(4, 2);
+        adjust_eyes(&mut objects, &game);
+        assert_close(objects[PLAYER].fov_radius, TORCH_RADIUS_IN_DARK_AREA as f32 - EYE_CONTRACT_RATE + EYE_DILATE_RATE);
+    }
+
+    fn potion(x: i32, y: i32) -> Object {
+        let mut potion = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
+        potion.item = Some(Item::Heal);
+        potion.weight = 1;
+        potion
+    }
+
+    fn dagger(x: i32, y: i32) -> Object {
+        let mut dagger = Object::new(x, y, '-', "dagger", colors::SKY, false);
+        dagger.item = Some(Item::Sword);
+        dagger.equipment = Some(Equipment{equipped: false, slot: Slot::LeftHand, max_hp_bonus: 0,
+            defense_bonus: 0, power_bonus: 2, regen_bonus: 0, cursed: false});
+        dagger
+    }
+
+    #[test]
+    fn picking_up_the_same_potion_stacks_it() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), potion(1, 1), potion(1, 1), potion(1, 1)];
+        for _ in 0..3 {
+            pick_item_up(1, &mut objects, &mut game);
+        }
+        assert_eq!(objects.len(), 1);
+        assert_eq!(game.inventory.len(), 1);
+        assert_eq!(game.inventory[0].count, 3);
+    }
+
+    #[test]
+    fn equipment_never_stacks() {
+        let mut game = game_with_map(HALL);
+        assert_eq!(add_to_inventory(dagger(0, 0), &mut game), 0);
+        assert_eq!(stack_index(&dagger(0, 0), &game.inventory), None);
+        assert_eq!(add_to_inventory(dagger(0, 0), &mut game), 1);
+        assert!(game.inventory.iter().all(|item| item.count == 1));
+    }
+
+    #[test]
+    fn using_one_from_a_stack_leaves_the_rest() {
+        let mut game = game_with_map(HALL);
+        add_to_inventory(potion(0, 0), &mut game);
+        add_to_inventory(potion(0, 0), &mut game);
+        // a potion used up is taken off its stack, like use_item does
+        let used = take_from_inventory(0, &mut game);
+        assert_eq!(used.count, 1);
+        assert_eq!(game.inventory[0].count, 1);
+        take_from_inventory(0, &mut game);
+        assert!(game.inventory.is_empty());
+    }
+
+    #[test]
+    fn dropping_one_from_a_stack_drops_just_one() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].set_pos(2, 2);
+        for _ in 0..3 {
+            add_to_inventory(potion(0, 0), &mut game);
+        }
+        drop_item(0, &mut game, &mut objects);
+        assert_eq!(game.inventory[0].count, 2);
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1].count, 1);
+        assert_eq!(objects[1].pos(), (2, 2));
+
+        // and picking it back up puts it back on the stack
+        pick_item_up(1, &mut objects, &mut game);
+        assert_eq!(game.inventory.len(), 1);
+        assert_eq!(game.inventory[0].count, 3);
+    }
+
+    #[test]
+    fn hunger_builds_up_twice_as_fast_in_the_dark() {
+        let mut game = game_with_map(HALL);
+        game.map[1][1].lit = true;
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].set_pos(1, 1);
+        tick_hunger(&mut objects, &mut game);
+        assert_eq!(game.hunger, 1);
+        objects[PLAYER].set_pos(2, 2);
+        tick_hunger(&mut objects, &mut game);
+        assert_eq!(game.hunger, 1 + HUNGER_DARK_RATE);
+    }
+
+    #[test]
+    fn each_hunger_threshold_is_announced_once() {
+        let mut game = game_with_map(HALL);
+        game.map[1][1].lit = true;
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].set_pos(1, 1);
+        game.hunger = HUNGER_HUNGRY - 1;
+        tick_hunger(&mut objects, &mut game);
+        tick_hunger(&mut objects, &mut game);
+        assert_eq!(game.log.len(), 1);
+        assert_eq!(game.log[0].0, "You are getting hungry.");
+        assert_eq!(hunger_status(&game), Some("Hungry"));
+
+        game.hunger = HUNGER_WEAK - 1;
+        tick_hunger(&mut objects, &mut game);
+        assert_eq!(game.log.last().unwrap().0, "You feel weak with hunger.");
+        assert_eq!(hunger_status(&game), Some("Weak"));
+    }
+
+    #[test]
+    fn starving_wastes_the_player_away() {
+        let mut game = game_with_map(HALL);
+        game.map[1][1].lit = true;
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].set_pos(1, 1);
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        game.hunger = HUNGER_STARVING - 1;
+        tick_hunger(&mut objects, &mut game);
+        assert!(game.log.iter().any(|(message, _)| message == "You are starving!"));
+        assert_eq!(hunger_status(&game), Some("Starving"));
+
+        // every so often while starving, it costs some hit points
+        for _ in 0..STARVATION_INTERVAL * 3 {
+            tick_hunger(&mut objects, &mut game);
+        }
+        let lost = hp - objects[PLAYER].fighter.unwrap().hp;
+        assert!((STARVATION_DAMAGE * 3..=STARVATION_DAMAGE * 4).contains(&lost), "lost {} hp", lost);
+        assert_eq!(game.stats.damage_taken, lost as u32);
+    }
+
+    fn villager(x: i32, y: i32, faction: Faction) -> Object {
+        let mut villager = Object::new(x, y, 'v', "villager", colors::LIGHT_SEPIA, true);
+        villager.alive = true;
+        villager.faction = faction;
+        villager.fighter = Some(Fighter{base_max_hp: 10, hp: 10, base_defense: 0, base_power: 1,
+            on_death: DeathCallback::Monster, xp: 0});
+        villager
+    }
+
+    #[test]
+    fn walking_into_an_ally_swaps_places() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), villager(3, 2, Faction::Ally)];
+        objects[PLAYER].set_pos(2, 2);
+        assert!(player_move_or_attack(1, 0, false, &mut game, &mut objects));
+        assert_eq!(objects[PLAYER].pos(), (3, 2));
+        assert_eq!(objects[1].pos(), (2, 2));
+        assert_eq!(objects[1].fighter.unwrap().hp, 10);
+        assert_eq!(game.log.last().unwrap().0, "You swap places with the villager.");
+    }
+
+    #[test]
+    fn walking_into_a_neutral_only_bumps_it() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), villager(3, 2, Faction::Neutral)];
+        objects[PLAYER].set_pos(2, 2);
+        assert!(player_move_or_attack(1, 0, false, &mut game, &mut objects));
+        assert_eq!(objects[PLAYER].pos(), (2, 2));
+        assert_eq!(objects[1].fighter.unwrap().hp, 10);
+        assert_eq!(game.log.last().unwrap().0, "You bump into the villager.");
+    }
+
+    #[test]
+    fn a_forced_step_attacks_neutrals_and_allies() {
+        // what happens once the player confirms they really mean to attack
+        for &faction in &[Faction::Neutral, Faction::Ally] {
+            let mut game = game_with_map(HALL);
+            let mut objects = vec![crate::new_player(), villager(3, 2, faction)];
+            objects[PLAYER].set_pos(2, 2);
+            assert!(player_move_or_attack(1, 0, true, &mut game, &mut objects));
+            assert_eq!(objects[PLAYER].pos(), (2, 2));
+            assert_eq!(objects[1].pos(), (3, 2));
+            assert!(objects[1].fighter.unwrap().hp < 10, "{:?} wasn't hurt", faction);
+        }
+    }
+
+    #[test]
+    fn object_index_agrees_with_is_blocked() {
+        let game = game_with_map(&[
+            "########",
+            "#......#",
+            "#.#....#",
+            "#......#",
+            "########",
+        ]);
+        let mut objects = vec![crate::new_player(), villager(3, 1, Faction::Neutral), potion(4, 2),
+            villager(5, 3, Faction::Hostile), potion(5, 3), dagger(6, 1)];
+        objects[PLAYER].set_pos(1, 1);
+        let index = ObjectIndex::new(&objects);
+        for x in 0..8 {
+            for y in 0..5 {
+                assert_eq!(index.is_blocked(x, y, &game.map, &objects), is_blocked(x, y, &game.map, &objects),
+                    "they disagree about ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn object_index_lists_a_tiles_objects_in_order() {
+        let objects = vec![crate::new_player(), potion(2, 2), villager(4, 1, Faction::Neutral), dagger(2, 2),
+            potion(2, 2)];
+        let index = ObjectIndex::new(&objects);
+        assert_eq!(index.at((2, 2)), &[1, 3, 4]);
+        assert_eq!(index.at((4, 1)), &[2]);
+        assert_eq!(index.at((0, 0)), &[PLAYER]);
+        assert!(index.at((5, 5)).is_empty());
+    }
+
+    #[test]
+    fn run_stats_count_damage_both_ways_and_kills() {
+        let mut game = game_with_map(HALL);
+        let mut objects = vec![crate::new_player(), villager(3, 2, Faction::Hostile)];
+        objects[PLAYER].set_pos(2, 2);
+        let damage = objects[PLAYER].power(&game) - objects[1].defense(&game);
+
+        player_move_or_attack(1, 0, false, &mut game, &mut objects);
+        assert_eq!(game.stats.damage_dealt, damage as u32);
+        assert_eq!(game.stats.damage_taken, 0);
+
+        {
+            let (player, villager) = mut_two(PLAYER, 1, &mut objects);
+            villager.attack(player, &mut game);
+        }
+        let taken = objects[1].power(&game) - objects[PLAYER].defense(&game);
+        assert_eq!(game.stats.damage_taken, taken as u32);
+        // a monster hurting another doesn't count as damage dealt by the player
+        assert_eq!(game.stats.damage_dealt, damage as u32);
+
+        while objects[1].alive {
+            player_move_or_attack(1, 0, false, &mut game, &mut objects);
+        }
+        assert_eq!(game.stats.kills.get("villager"), Some(&1));
+        // every hit counted, down to the last of its 10 hit points
+        assert!(game.stats.damage_dealt >= 10);
+    }
+
+    #[test]
+    fn run_stats_count_steps_into_the_light_and_the_dark() {
+        let mut game = game_with_map(HALL);
+        game.settings.warn_light = false;
+        game.map[3][2].lit = true;
+        let mut objects = vec![crate::new_player()];
+        objects[PLAYER].set_pos(1, 2);
+        walk_player(1, 0, &mut game, &mut objects);
+        walk_player(1, 0, &mut game, &mut objects);
+        assert_eq!((game.stats.steps_dark, game.stats.steps_lit), (1, 1));
+        // walking into a wall isn't a step at all
+        walk_player(0, -2, &mut game, &mut objects);
+        assert_eq!((game.stats.steps_dark, game.stats.steps_lit), (1, 1));
+        // and moving by hand counts the same
+        player_move_or_attack(1, 0, false, &mut game, &mut objects);
+        assert_eq!((game.stats.steps_dark, game.stats.steps_lit), (2, 1));
+    }
+}
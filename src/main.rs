@@ -24,12 +24,14 @@ mod render;
 use render::*;
 // ai is a separate file that hold functions related to enemy ai
 mod ai;
-use ai::*;
 mod spells;
+// fov is a separate file with a field of view that doesn't need libtcod
+mod fov;
+// profile is a separate file that runs the game logic without a window and times it
+mod profile;
 
 use std::io::{Read, Write};
 use std::fs::File;
-use std::error::Error;
 use tcod::console::*;
 use tcod::colors::{self};
 use tcod::map::{Map as FovMap}; // the 'Map as FovMap' section renames the tcod fov map
@@ -39,7 +41,16 @@ use tcod::input::{self, Event};
 
 /// main function of the game, starts with initializers, then moves into the main game loop
 fn main() {
-    
+
+    // `roguelike --profile [turns] [seed]` simulates a game without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map_or(false, |arg| arg == "--profile") {
+        let turns = args.get(2).and_then(|arg| arg.parse().ok()).unwrap_or(PROFILE_TURNS);
+        let seed = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(PROFILE_SEED);
+        profile::run_profile(turns, seed);
+        return;
+    }
+
     let root = Root::initializer()
         .font("cp437_10x10.png", FontLayout::AsciiInRow) // set up a font. this can be in various formats, must be in the root, next to Cargo.toml        
         .font_type(FontType::Greyscale)
@@ -53,8 +64,9 @@ fn main() {
         root: root,
         con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT), // create offscreen console for the map
         panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT), // create offscreen console for the gui
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        fov: Box::new(FovMap::new(MAP_WIDTH, MAP_HEIGHT)),
         mouse: Default::default(),
+        camera: (0, 0),
     };
 
     main_menu(&mut tcod);
@@ -68,6 +80,7 @@ fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Obj
     use PlayerAction::*;
 
     let player_alive = objects[PLAYER].alive;
+    let diagonals = !game.settings.orthogonal_movement;
     match (key, player_alive) {
         (Key {code: Enter, alt: true, ..}, _) => {
             // Alt+Enter: toggle fullscreen
@@ -76,65 +89,150 @@ fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Obj
             DidntTakeTurn
         },
         (Key {code: Escape, ..}, _) => Exit, // exit game
-        // movement keys
-        (Key {code: Up, ..}, true) | (Key {code: NumPad8, ..}, true) => {
-            player_move_or_attack(0, -1, game, objects);
-            TookTurn
+        // shift and a direction runs that way until something interesting happens
+        (Key {code: Up, shift: true, ..}, true) | (Key {printable: 'k', shift: true, ..}, true) => {
+            run(0, -1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: Down, shift: true, ..}, true) | (Key {printable: 'j', shift: true, ..}, true) => {
+            run(0, 1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: Left, shift: true, ..}, true) | (Key {printable: 'h', shift: true, ..}, true) => {
+            run(-1, 0, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: Right, shift: true, ..}, true) | (Key {printable: 'l', shift: true, ..}, true) => {
+            run(1, 0, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: Home, shift: true, ..}, true) | (Key {printable: 'y', shift: true, ..}, true) if diagonals => {
+            run(-1, -1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: PageUp, shift: true, ..}, true) | (Key {printable: 'u', shift: true, ..}, true) if diagonals => {
+            run(1, -1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: End, shift: true, ..}, true) | (Key {printable: 'b', shift: true, ..}, true) if diagonals => {
+            run(-1, 1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {code: PageDown, shift: true, ..}, true) | (Key {printable: 'n', shift: true, ..}, true) if diagonals => {
+            run(1, 1, tcod, game, objects);
+            DidntTakeTurn
+        },
+        // movement keys (arrows, numpad or vi-keys), hold ctrl to force an attack on an ally or neutral
+        (Key {code: Up, ctrl, ..}, true) | (Key {code: NumPad8, ctrl, ..}, true) |
+        (Key {printable: 'k', ctrl, ..}, true) => {
+            player_step(0, -1, ctrl, tcod, game, objects)
         },
-        (Key {code: Down, ..}, true) | (Key {code: NumPad2, ..}, true) => {
-            player_move_or_attack(0, 1, game, objects);
-            TookTurn
+        (Key {code: Down, ctrl, ..}, true) | (Key {code: NumPad2, ctrl, ..}, true) |
+        (Key {printable: 'j', ctrl, ..}, true) => {
+            player_step(0, 1, ctrl, tcod, game, objects)
         },
-        (Key {code: Left, ..}, true) | (Key {code: NumPad4, ..}, true) => {
-            player_move_or_attack(-1, 0, game, objects);
-            TookTurn
+        (Key {code: Left, ctrl, ..}, true) | (Key {code: NumPad4, ctrl, ..}, true) |
+        (Key {printable: 'h', ctrl, ..}, true) => {
+            player_step(-1, 0, ctrl, tcod, game, objects)
         },
-        (Key {code: Right, ..}, true) | (Key {code: NumPad6, ..}, true) => {
-            player_move_or_attack(1, 0, game, objects);
-            TookTurn
+        (Key {code: Right, ctrl, ..}, true) | (Key {code: NumPad6, ctrl, ..}, true) |
+        (Key {printable: 'l', ctrl, ..}, true) => {
+            player_step(1, 0, ctrl, tcod, game, objects)
         },
-        (Key {code: Home, ..}, true) | (Key {code: NumPad7, ..}, true) => {
-            player_move_or_attack(-1, -1, game, objects);
-            TookTurn
+        (Key {code: Home, ctrl, ..}, true) | (Key {code: NumPad7, ctrl, ..}, true) |
+        (Key {printable: 'y', ctrl, ..}, true) if diagonals => {
+            player_step(-1, -1, ctrl, tcod, game, objects)
         },
-        (Key {code: PageUp, ..}, true) | (Key {code: NumPad9, ..}, true) => {
-            player_move_or_attack(1, -1, game, objects);
-            TookTurn
+        (Key {code: PageUp, ctrl, ..}, true) | (Key {code: NumPad9, ctrl, ..}, true) |
+        (Key {printable: 'u', ctrl, ..}, true) if diagonals => {
+            player_step(1, -1, ctrl, tcod, game, objects)
         },
-        (Key {code: End, ..}, true) | (Key {code: NumPad1, ..}, true) => {
-            player_move_or_attack(-1, 1, game, objects);
-            TookTurn
+        (Key {code: End, ctrl, ..}, true) | (Key {code: NumPad1, ctrl, ..}, true) |
+        (Key {printable: 'b', ctrl, ..}, true) if diagonals => {
+            player_step(-1, 1, ctrl, tcod, game, objects)
         },
-        (Key {code: PageDown, ..}, true) | (Key {code: NumPad3, ..}, true) => {
-            player_move_or_attack(1, 1, game, objects);
-            TookTurn
+        (Key {code: PageDown, ctrl, ..}, true) | (Key {code: NumPad3, ctrl, ..}, true) |
+        (Key {printable: 'n', ctrl, ..}, true) if diagonals => {
+            player_step(1, 1, ctrl, tcod, game, objects)
         },
         (Key {code: NumPad5, ..}, true) => {
             TookTurn // do nothing, i.e. wait for the monster to come to you
         },
+        (Key {printable: 'r', ..}, true) => {
+            // rest until healed or interrupted; the turns are taken inside
+            rest(tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {printable: 'o', ..}, true) => {
+            // explore until interrupted; the turns are taken inside
+            auto_explore(tcod, game, objects);
+            DidntTakeTurn
+        },
+        (Key {printable: 'm', shift: true, ..}, _) => {
+            // a shrunken map of everything explored on this level
+            minimap_screen(objects, game, &mut tcod.root);
+            DidntTakeTurn
+        },
+        (Key {printable: 'm', ..}, _) => {
+            // look back through every message so far
+            let player_lit = game.map[objects[PLAYER].x as usize][objects[PLAYER].y as usize].lit;
+            message_log_screen(game, player_lit, &mut tcod.root);
+            DidntTakeTurn
+        },
+        (Key {printable: '?', ..}, _) => {
+            // look up the monsters and items met so far
+            let player_lit = game.map[objects[PLAYER].x as usize][objects[PLAYER].y as usize].lit;
+            bestiary_screen(game, player_lit, &mut tcod.root);
+            DidntTakeTurn
+        },
+        (Key {printable: 'x', ..}, _) => {
+            // look around with a cursor
+            look_mode(tcod, objects, game);
+            DidntTakeTurn
+        },
         (Key {printable: 'g', ..}, true) => {
-            // pick up an item
+            // pick up an item, or failing that, take what's in the remains underfoot
             let item_id = objects.iter().position(|object| {
                 object.pos() == objects[PLAYER].pos() && object.item.is_some()
             });
+            let corpse_id = objects.iter().position(|object| {
+                object.pos() == objects[PLAYER].pos() && object.corpse && !object.inventory.is_empty()
+            });
             if let Some(item_id) = item_id {
                 pick_item_up(item_id, objects, game);
+            } else if let Some(corpse_id) = corpse_id {
+                search_corpse(corpse_id, objects, game);
             }
             DidntTakeTurn
         },
         (Key {printable: 'i', ..}, true) => {
             // show the inventory: if an item is selcted, use it
             let inventory_index = inventory_menu(game,
+                                                 &objects[PLAYER],
                                                  "Press the key next to an item to use it, or any other to cancel. \n",
                                                   &mut tcod.root);
             if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, objects, game, tcod)
+                use_item(inventory_index, objects, game, tcod);
             }
             DidntTakeTurn
         },
+        (Key {printable: 'q', ..}, true) => {
+            // drink the first healing potion carried, without going through the inventory
+            match game.inventory.iter().position(|item| item.item == Some(Item::Heal)) {
+                Some(inventory_index) => match use_item(inventory_index, objects, game, tcod) {
+                    UseResult::Cancelled => DidntTakeTurn,
+                    _ => TookTurn,
+                },
+                None => {
+                    game.log.add("You have no healing potions.", colors::WHITE);
+                    DidntTakeTurn
+                }
+            }
+        },
         (Key {printable: 'd', ..}, true) => {
             // show the inventory; if an item is selcted, drop it
             let inventory_index = inventory_menu(game
+                , &objects[PLAYER]
                 , "Press the key next to an item to drop it, or any other to cancel. \n"
                 , &mut tcod.root);
             if let Some(inventory_index) = inventory_index {
@@ -142,13 +240,43 @@ fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Obj
             }
             DidntTakeTurn
         },
+        (Key {printable: 'f', ..}, true) => fire_bow(tcod, game, objects),
+        (Key {printable: 't', shift: true, ..}, true) => {
+            // show the inventory; if an item is selected, throw it at a target tile
+            let inventory_index = inventory_menu(game,
+                                                 &objects[PLAYER],
+                                                 "Press the key next to an item to throw it, or any other to cancel. \n",
+                                                 &mut tcod.root);
+            if let Some(inventory_index) = inventory_index {
+                let potion = game.inventory[inventory_index].item.map_or(false, |item| item.is_potion());
+                let splash = if potion { Some(SPLASH_RADIUS) } else { None };
+                game.log.add("Left-click a target tile (or move the cursor and press Enter) to throw at, or right-click/Escape to cancel.",
+                    colors::LIGHT_CYAN);
+                if let Some(target) = target_tile(tcod, objects, game, Some(THROW_RANGE), splash) {
+                    throw_item(inventory_index, target, objects, game);
+                    return TookTurn
+                }
+            }
+            DidntTakeTurn
+        },
         (Key {printable: ',' ,shift: true, ..}, true) => {
             // go down stairs, if player is on them
             let player_on_stairs = objects.iter().any(|object| {
                 object.pos() == objects[PLAYER].pos() && object.name == "stairs"
             });
             if player_on_stairs {
-                next_level(tcod, objects, game);
+                take_stairs(tcod, objects, game)
+            } else {
+                DidntTakeTurn
+            }
+        },
+        (Key {printable: '.' ,shift: true, ..}, true) => {
+            // go up stairs, if player is on them
+            let player_on_stairs = objects.iter().any(|object| {
+                object.pos() == objects[PLAYER].pos() && object.name == "up stairs"
+            });
+            if player_on_stairs {
+                previous_level(tcod, objects, game);
             }
             DidntTakeTurn
         },
@@ -158,6 +286,10 @@ fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Obj
             let level = player.level;
             let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
             if let Some(fighter) = player.fighter.as_ref() {
+                let equipped: Vec<String> = game.inventory.iter()
+                    .filter_map(|item| item.equipment.filter(|e| e.equipped).map(|e| format!("{}: {}", e.slot, item.name)))
+                    .collect();
+                let equipped = if equipped.is_empty() { "nothing".to_string() } else { equipped.join("\n") };
                 let msg = format!("Character information
 
 Level: {}
@@ -166,7 +298,17 @@ Experience to level up: {}
 
 Maximum HP: {}
 Attack: {}
-Defense: {}", level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game));
+Defense: {}
+Gold: {}
+
+Equipped:
+{}
+
+{}
+
+Difficulty: {}
+Seed: {}", level, fighter.xp, level_up_xp, player.max_hp(game), player.power(game), player.defense(game), game.gold,
+                    equipped, game.stats.describe(), game.difficulty, game.seed);
                 msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
             }
             DidntTakeTurn
@@ -175,67 +317,186 @@ Defense: {}", level, fighter.xp, level_up_xp, player.max_hp(game), player.power(
     }
 }
 
-fn new_game (tcod: &mut Tcod) -> (Vec<Object>, Game) {
-    // create object representing the player
+/// move or attack in a direction, asking before attacking a neutral unless the attack is forced
+fn player_step(dx: i32, dy: i32, force: bool, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
+    let mut force = force;
+    if let Some(target_id) = fighter_at(dx, dy, objects) {
+        if !force && objects[target_id].name == "shopkeeper" {
+            visit_shop(target_id, tcod, game, objects);
+            return PlayerAction::DidntTakeTurn;
+        }
+        if !force && objects[target_id].faction == Faction::Neutral {
+            let prompt = format!("Really attack the {}?\n", objects[target_id].name);
+            if menu(&prompt, &["Yes", "No"], CONFIRM_SCREEN_WIDTH, &mut tcod.root) != Some(0) {
+                return PlayerAction::DidntTakeTurn;
+            }
+            force = true;
+        }
+    }
+    if player_move_or_attack(dx, dy, force, game, objects) {
+        PlayerAction::TookTurn
+    } else {
+        PlayerAction::DidntTakeTurn
+    }
+}
+
+/// shoot an arrow at a monster with the equipped bow. returns whether it took a turn
+fn fire_bow(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
+    let has_bow = get_equipped_in_slot(Slot::RightHand, &game.inventory)
+        .map_or(false, |index| game.inventory[index].item == Some(Item::Bow));
+    if !has_bow {
+        game.log.add("You need a bow in your right hand to shoot.", colors::WHITE);
+        return PlayerAction::DidntTakeTurn;
+    }
+    if game.arrows <= 0 {
+        game.log.add("You're out of arrows.", colors::RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+    if closest_monster(BOW_RANGE as i32, objects, game, tcod).is_none() {
+        game.log.add("There's nothing in range to shoot at.", colors::WHITE);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    game.log.add("Left-click an enemy (or move the cursor and press Enter) to shoot, or right-click/Escape to cancel.",
+        colors::LIGHT_CYAN);
+    let target_id = match target_monster(tcod, objects, game, Some(BOW_RANGE)) {
+        Some(target_id) => target_id,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+    game.arrows -= 1;
+    game.animations.push(Animation::Projectile{from: objects[PLAYER].pos(), to: objects[target_id].pos(),
+        char: '-', color: colors::LIGHT_SEPIA});
+    let damage = objects[PLAYER].power(game) - objects[target_id].defense(game);
+    if damage > 0 {
+        game.log.add(format!("Your arrow hits the {} for {} hit points.", objects[target_id].name, damage), colors::WHITE);
+        game.stats.damage_dealt += damage as u32;
+        if let Some(xp) = objects[target_id].take_damage(damage, game) {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+    } else {
+        game.log.add(format!("Your arrow glances off the {}.", objects[target_id].name), colors::WHITE);
+    }
+    PlayerAction::TookTurn
+}
+
+/// buy things from a shopkeeper until the player closes the menu
+fn visit_shop(shopkeeper_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    loop {
+        if objects[shopkeeper_id].inventory.is_empty() {
+            game.log.add("The shopkeeper has nothing left to sell.", colors::LIGHT_GREY);
+            return;
+        }
+        let options: Vec<String> = objects[shopkeeper_id].inventory.iter().map(|item| {
+            format!("{} ({} gold)", item_name(item, game), item.item.map_or(0, Item::price))
+        }).collect();
+        let header = format!("You have {} gold. What would you like to buy?\n", game.gold);
+        let index = match menu(&header, &options, SHOP_SCREEN_WIDTH, &mut tcod.root) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let price = objects[shopkeeper_id].inventory[index].item.map_or(0, Item::price);
+        if price > game.gold {
+            game.log.add(format!("You can't afford the {}.", item_name(&objects[shopkeeper_id].inventory[index], game)), colors::RED);
+        } else if game.inventory.len() >= 26 && stack_index(&objects[shopkeeper_id].inventory[index], &game.inventory).is_none() {
+            game.log.add("Your inventory is full.", colors::RED);
+        } else {
+            let item = objects[shopkeeper_id].inventory.remove(index);
+            game.gold -= price;
+            game.log.add(format!("You buy the {} for {} gold.", item_name(&item, game), price), colors::GOLD);
+            add_to_inventory(item, game);
+        }
+    }
+}
+
+/// create the object representing the player
+fn new_player() -> Object {
     let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
     player.alive = true;
-    player.fov_radius = 5; // start here, see how it goes
+    player.faction = Faction::Player;
+    player.fov_radius = 5.0; // start here, see how it goes
     player.fighter = Some(Fighter{base_max_hp: 100, hp: 100, base_defense: 1, base_power: 2, on_death: DeathCallback::Player, xp: 0});
+    player
+}
 
+fn new_game (tcod: &mut Tcod, settings: Settings, seed: u32, difficulty: Difficulty, max_depth: Option<u32>) -> (Vec<Object>, Game) {
     // the list of objects with just the player
-    let mut objects = vec![player];
-    let level = 1;
-    
-    let mut game = Game {
+    let mut objects = vec![new_player()];
+
+    // the prologue is played as level 0, before the procedural dungeon begins.
+    // if it can't be loaded, just start the dungeon
+    let prologue = if settings.play_prologue {
+        make_map_from_file(PROLOGUE_FILE, &mut objects, &mut level_rng(seed, 0)).ok()
+    } else {
+        None
+    };
+    let (map, level) = match prologue {
+        Some(map) => (map, 0),
         // generate map (at thsi point it's not drawn to the screen)
-        // map: make_map(&mut objects, level),
-        map: make_map_debug(&mut objects, level), // debug version, used for testing stuff
-        // create the list of game messages and their colors, starts empty
-        log: vec![],
-        inventory: vec![],
-        dungeon_level: level,
+        None => (make_level_map(&mut objects, 1, seed, difficulty), 1),
     };
 
+    let mut game = Game::new(map, settings, seed, difficulty, max_depth);
+    game.dungeon_level = level;
+    game.scroll_labels = make_scroll_labels();
+
     // initial equipment: a dagger
     let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
     dagger.item = Some(Item::Sword);
+    dagger.weight = 2;
     dagger.equipment = Some(Equipment {
         equipped: true,
         slot: Slot::LeftHand,
         max_hp_bonus: 0,
         defense_bonus: 0,
-        power_bonus: 2
+        power_bonus: 2,
+        regen_bonus: 0,
+        cursed: false,
     });
     game.inventory.push(dagger);
 
-    initialize_fov(&game.map, tcod);
+    initialize_fov(&game, tcod);
 
     // a warm welcoming message!
     game.log.add("Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.", colors::RED);
+    if game.dungeon_level == 0 {
+        game.log.add("It is very dark here. Wait a moment, and your eyes will adjust to the dark.", colors::LIGHT_GREY);
+        game.log.add("Light makes the world clearer, but your eyes take time to adjust back.", colors::LIGHT_GREY);
+    }
 
     (objects, game)
 }
 
-fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
+/// play until the player quits, saving into the given slot on the way out
+fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod, slot: usize) {
     // force FOV 'recompute' first time through the game loop
     let mut previous_player_position = (-1, -1);
-
-    let mut key = Default::default();
+    let mut was_dazzled = false;
 
     while !tcod.root.window_closed() {
+        let mut key = Default::default();
+        let mut travel_click = false;
         match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, Event::Mouse(m))) => tcod.mouse = m,
+            Some((_, Event::Mouse(m))) => {
+                tcod.mouse = m;
+                travel_click = m.rbutton_pressed;
+            },
             Some((_, Event::Key(k))) => key = k,
-            _ => key = Default::default(),
+            _ => {},
         }
 
         // render the screen
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos()); // we may need to update this to account for changing fovs
+        // being dazzled shrinks the fov and whitens the map, and it has to be redrawn again once it wears off
+        let dazzled = objects[PLAYER].has_status(StatusKind::Dazzled);
+        let fov_recompute = previous_player_position != (objects[PLAYER].pos()) || dazzled || was_dazzled; // we may need to update this to account for changing fovs
+        was_dazzled = dazzled;
         render_all(tcod, objects, game, fov_recompute); 
 
         tcod.root.flush();
+        note_seen(objects, game, &tcod.fov);
 
-        // level up if needed
+        // level up if needed, counting anything just explored
+        grant_exploration_xp(objects, game);
         level_up(objects, game, tcod);
 
         // erase all objects at their old locations, before they move
@@ -243,43 +504,318 @@ fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
             object.clear(&mut tcod.con)
         }
 
+        // right-clicking an explored tile walks the player there
+        if travel_click && objects[PLAYER].alive {
+            let target = to_map_coordinates(tcod, tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+            travel(target, tcod, game, objects);
+        }
+
         // handle keys and exit game if needed
         previous_player_position = objects[PLAYER].pos();
         let player_action = handle_keys(key, tcod, game, objects);
         if player_action == PlayerAction::Exit {
-            save_game(objects, game).unwrap();
+            if let Err(e) = save_game(objects, game, slot) {
+                msgbox(&format!("\nThe game could not be saved: {}\n", e), 24, &mut tcod.root);
+            }
             break
         }
 
-        // let monsters take their turn
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, game, objects, &tcod.fov);
-                }
-            }
+        if world_moves_on(player_action, objects) {
+            play_animations(tcod, objects, game);
+            end_turn(tcod, game, objects);
         }
 
-        // update player fov_radius if necessary
-        // we do this after the monsters take their turn (for now); fov is recomputed in the render_all function
-        // this way the player can predict what the monster is going to do based on the fov when they take a turn
-        // instead of re-computing in between player and monster actions
-        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            if game.map[objects[PLAYER].x as usize][objects[PLAYER].y as usize].lit {
-                objects[PLAYER].fov_radius = TORCH_RADIUS_IN_LIT_AREA;
-            } else { // player is in dark area
-                objects[PLAYER].fov_radius += 1;
-                if objects[PLAYER].fov_radius > TORCH_RADIUS_IN_DARK_AREA {
-                    objects[PLAYER].fov_radius = TORCH_RADIUS_IN_DARK_AREA;
-                }
+        // the run is over when the player dies or leaves the bottom of the dungeon
+        if !objects[PLAYER].alive || player_action == PlayerAction::Won {
+            end_run(tcod, game, objects, slot);
+            break
+        }
+
+        // dead monsters drop anything they were carrying, escaped monsters take it with them
+        drop_carried_items(objects, game);
+        remove_escaped(objects);
+    }
+}
+
+/// show how the run went, after the player died or won. with permadeath on,
+/// the run is over for good and the save in its slot goes away
+fn end_run(tcod: &mut Tcod, game: &mut Game, objects: &[Object], slot: usize) {
+    render_all(tcod, objects, game, false);
+    tcod.root.flush();
+    let won = objects[PLAYER].alive;
+    let save = if !game.settings.permadeath {
+        "".to_string()
+    } else {
+        match std::fs::remove_file(save_path(slot)) {
+            Ok(()) if won => "The run is over, and its save is gone.".to_string(),
+            Ok(()) => "Your save is gone with you.".to_string(),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => "".to_string(), // never saved
+            Err(e) => format!("Your save could not be deleted: {}", e),
+        }
+    };
+    let (title, title_color) = if won {
+        (format!("You made it out of the dark from {}!", level_name(game.dungeon_level)), colors::GOLD)
+    } else {
+        (format!("You died on {}.", level_name(game.dungeon_level)), colors::RED)
+    };
+
+    let player = &objects[PLAYER];
+    let deepest = game.levels.keys().cloned().chain(Some(game.dungeon_level)).max().unwrap_or(0);
+    let depth = game.max_depth.map_or("endless".to_string(), |depth| format!("{} levels", depth));
+    let body = format!("Deepest level reached: {} (of {})
+Turns taken: {}
+{}
+
+Character level: {}
+Maximum HP: {}
+Attack: {}
+Defense: {}
+Gold: {}
+
+Difficulty: {}
+Seed: {}
+
+{}", deepest, depth, game.turn_count, game.stats.describe(), player.level, player.max_hp(game), player.power(game),
+        player.defense(game), game.gold, game.difficulty, game.seed, save);
+    let player_lit = game.map[player.x as usize][player.y as usize].lit;
+    run_summary_screen(&title, title_color, &body, player_lit, &mut tcod.root);
+}
+
+/// whether the world takes its turn after what the player just did. only actions
+/// that took a turn count, so the turn counter only goes up for those
+fn world_moves_on(player_action: PlayerAction, objects: &[Object]) -> bool {
+    objects[PLAYER].alive && player_action == PlayerAction::TookTurn
+}
+
+/// everything that happens in the world after the player has taken their turn
+fn end_turn(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    simulate_turn(game, objects, &tcod.fov);
+    play_animations(tcod, objects, game);
+}
+
+/// walk to a tile the player has already explored, one turn per step,
+/// until they get there, a monster shows up, or a key is pressed
+fn travel(target: (i32, i32), tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    let (x, y) = target;
+    if x < 0 || y < 0 || x >= MAP_WIDTH || y >= MAP_HEIGHT || !game.map[x as usize][y as usize].explored {
+        return;
+    }
+    if monster_in_view(objects, &tcod.fov) {
+        game.log.add("You can't travel with enemies nearby.", colors::RED);
+        return;
+    }
+    let mut path = match find_path(objects[PLAYER].pos(), target, MAP_WIDTH * MAP_HEIGHT, game, objects) {
+        Some(path) => path,
+        None => {
+            game.log.add("You can't find a way there.", colors::LIGHT_GREY);
+            return;
+        },
+    };
+
+    while let Some((step_x, step_y)) = path.pop() {
+        if input::check_for_event(input::KEY_PRESS).is_some() {
+            game.log.add("You stop travelling.", colors::LIGHT_GREY);
+            break;
+        }
+
+        for object in objects.iter() {
+            object.clear(&mut tcod.con)
+        }
+        let (player_x, player_y) = objects[PLAYER].pos();
+        if is_blocked(step_x, step_y, &game.map, objects) {
+            game.log.add("Something is in the way.", colors::LIGHT_GREY);
+            break;
+        }
+        if steps_into_light(step_x, step_y, game, objects) {
+            game.log.add("You stop at the edge of the light.", colors::LIGHT_YELLOW);
+            break;
+        }
+        walk_player(step_x - player_x, step_y - player_y, game, objects);
+        end_turn(tcod, game, objects);
+        drop_carried_items(objects, game);
+        remove_escaped(objects);
+        if !objects[PLAYER].alive {
+            break;
+        }
+
+        // recompute the fov every step, just like walking by hand
+        render_all(tcod, objects, game, true);
+        tcod.root.flush();
+        if monster_in_view(objects, &tcod.fov) {
+            game.log.add("You stop travelling: there's a monster in view.", colors::RED);
+            break;
+        }
+    }
+}
+
+/// walk towards the nearest unexplored tile until something interesting happens
+fn auto_explore(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    loop {
+        if monster_in_view(objects, &tcod.fov) {
+            game.log.add("You stop exploring: there's a monster in view.", colors::RED);
+            break;
+        }
+        let player_pos = objects[PLAYER].pos();
+        let escape = match input::check_for_event(input::KEY_PRESS) {
+            Some((_, Event::Key(key))) => key.code == tcod::input::KeyCode::Escape,
+            _ => false,
+        };
+        if escape {
+            game.log.add("You stop exploring.", colors::LIGHT_GREY);
+            break;
+        }
+        let (step_x, step_y) = match step_towards_unexplored(game, objects) {
+            Some(step) => step,
+            None => {
+                game.log.add("There's nowhere left to explore.", colors::LIGHT_GREY);
+                break;
+            },
+        };
+
+        if steps_into_light(step_x, step_y, game, objects) {
+            game.log.add("You stop at the edge of the light.", colors::LIGHT_YELLOW);
+            break;
+        }
+
+        for object in objects.iter() {
+            object.clear(&mut tcod.con)
+        }
+        walk_player(step_x - player_pos.0, step_y - player_pos.1, game, objects);
+        end_turn(tcod, game, objects);
+        drop_carried_items(objects, game);
+        remove_escaped(objects);
+        if !objects[PLAYER].alive {
+            break;
+        }
+
+        // recompute the fov every step, so whatever the player's eyes pick up gets explored
+        render_all(tcod, objects, game, true);
+        tcod.root.flush();
+
+        let player_pos = objects[PLAYER].pos();
+        let item_underfoot = objects.iter().any(|object| object.pos() == player_pos && object.item.is_some());
+        if item_underfoot {
+            game.log.add("You stop exploring: there's something here.", colors::LIGHT_GREY);
+            break;
+        }
+    }
+}
+
+/// run in a direction, one turn per step, until the player hits something, a monster
+/// comes into view, there's something underfoot, or the corridor opens up or branches
+fn run(dx: i32, dy: i32, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    if monster_in_view(objects, &tcod.fov) {
+        game.log.add("You can't run with enemies nearby.", colors::RED);
+        return;
+    }
+    let mut openings = open_neighbours(objects[PLAYER].pos(), game);
+    let mut first_step = true;
+    loop {
+        let (x, y) = objects[PLAYER].pos();
+        if is_blocked(x + dx, y + dy, &game.map, objects) {
+            break;
+        }
+        if steps_into_light(x + dx, y + dy, game, objects) {
+            game.log.add("You stop at the edge of the light.", colors::LIGHT_YELLOW);
+            break;
+        }
+        if input::check_for_event(input::KEY_PRESS).is_some() {
+            game.log.add("You stop running.", colors::LIGHT_GREY);
+            break;
+        }
+
+        for object in objects.iter() {
+            object.clear(&mut tcod.con)
+        }
+        walk_player(dx, dy, game, objects);
+        end_turn(tcod, game, objects);
+        drop_carried_items(objects, game);
+        remove_escaped(objects);
+        if !objects[PLAYER].alive || objects[PLAYER].pos() == (x, y) {
+            break;
+        }
+
+        // recompute the fov every step, so a monster the player's eyes pick up stops them
+        render_all(tcod, objects, game, true);
+        tcod.root.flush();
+        if monster_in_view(objects, &tcod.fov) {
+            game.log.add("You stop running: there's a monster in view.", colors::RED);
+            break;
+        }
+        let player_pos = objects[PLAYER].pos();
+        if objects.iter().any(|object| object.pos() == player_pos && (object.item.is_some() || object.name.ends_with("stairs"))) {
+            break;
+        }
+        // a side passage or a room opening up changes how many ways there are to go
+        let now_open = open_neighbours(player_pos, game);
+        if !first_step && now_open != openings {
+            break;
+        }
+        openings = now_open;
+        first_step = false;
+    }
+}
+
+/// how many of the tiles around a position can be walked on
+fn open_neighbours((x, y): (i32, i32), game: &Game) -> usize {
+    let mut open = 0;
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (nx, ny) = (x + dx, y + dy);
+            if (dx, dy) != (0, 0) && nx >= 0 && ny >= 0 && nx < MAP_WIDTH && ny < MAP_HEIGHT &&
+                !game.map[nx as usize][ny as usize].blocked {
+                open += 1;
             }
         }
     }
+    open
+}
+
+/// pass turns until the player is fully healed, a monster shows up, or Escape is pressed
+fn rest(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    if monster_in_view(objects, &tcod.fov) {
+        game.log.add("You can't rest with enemies nearby.", colors::RED);
+        return;
+    }
+    game.log.add("You settle down to rest...", colors::LIGHT_GREY);
+    loop {
+        if objects[PLAYER].fighter.map_or(true, |f| f.hp >= objects[PLAYER].max_hp(game)) {
+            game.log.add("You feel rested.", colors::LIGHT_GREY);
+            break;
+        }
+        let escape = match input::check_for_event(input::KEY_PRESS) {
+            Some((_, Event::Key(key))) => key.code == tcod::input::KeyCode::Escape,
+            _ => false,
+        };
+        if escape {
+            game.log.add("You stop resting.", colors::LIGHT_GREY);
+            break;
+        }
+
+        for object in objects.iter() {
+            object.clear(&mut tcod.con)
+        }
+        end_turn(tcod, game, objects);
+        drop_carried_items(objects, game);
+        remove_escaped(objects);
+        if !objects[PLAYER].alive {
+            break;
+        }
+
+        // redraw so the player can watch the turns go by, and so the fov is up to date
+        render_all(tcod, objects, game, true);
+        tcod.root.flush();
+        if monster_in_view(objects, &tcod.fov) {
+            game.log.add("You stop resting: something is coming!", colors::RED);
+            break;
+        }
+    }
 }
 
 fn main_menu(tcod: &mut Tcod) {
     let img = tcod::image::Image::from_file("menu_background.png")
         .ok().expect("Background image not found");
+    let mut settings = Settings::new();
     
     while !tcod.root.window_closed() {
         // make sure root is clear
@@ -296,29 +832,89 @@ fn main_menu(tcod: &mut Tcod) {
             BackgroundFlag::None, TextAlignment::Center, "By Nuzcraft");
 
         // show the options and wait for the player's choice
-        let choices = &["Play a new game", "Continue last game", "Quit"];
+        let choices = &["Play a new game", "Continue last game", "Options", "Quit"];
         let choice = menu("", choices, 24, &mut tcod.root);
 
         match choice {
             Some(0) => {
-                // new game
-                let (mut objects, mut game) = new_game(tcod);
-                play_game(&mut objects, &mut game, tcod);
+                // new game, saved into whichever slot the player picks
+                let slot = match slot_menu("Choose a slot to save the new game in:\n", &mut tcod.root) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                // play a random dungeon, or one shared by someone else
+                let seed = match menu("", &["Random dungeon", "Enter a seed"], 24, &mut tcod.root) {
+                    Some(0) => rand::random(),
+                    Some(1) => match number_prompt("Type the seed and press Enter:", 24, &mut tcod.root) {
+                        Some(seed) => seed,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                let difficulty = match menu("Choose a difficulty:\n", &["Easy", "Normal", "Hard"], 24, &mut tcod.root) {
+                    Some(0) => Difficulty::Easy,
+                    Some(1) => Difficulty::Normal,
+                    Some(2) => Difficulty::Hard,
+                    _ => continue,
+                };
+                // how deep the run goes before it's won, if ever
+                let depth_options = &[format!("Down to level {}", FINAL_DUNGEON_LEVEL), "Choose the depth".to_string(), "Endless".to_string()];
+                let max_depth = match menu("How deep is the dungeon?\n", depth_options, 24, &mut tcod.root) {
+                    Some(0) => Some(FINAL_DUNGEON_LEVEL),
+                    Some(1) => match number_prompt("Type the deepest level and press Enter:", 24, &mut tcod.root) {
+                        Some(depth) => Some(depth.max(1)),
+                        None => continue,
+                    },
+                    Some(2) => None,
+                    _ => continue,
+                };
+                let (mut objects, mut game) = new_game(tcod, settings, seed, difficulty, max_depth);
+                play_game(&mut objects, &mut game, tcod, slot);
             }
             Some(1) => {
                 // load game
-                match load_game() {
+                let slot = match slot_menu("Choose a saved game to continue:\n", &mut tcod.root) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                match load_game(slot) {
                     Ok((mut objects, mut game)) => {
-                        initialize_fov(&game.map, tcod);
-                        play_game(&mut objects, &mut game, tcod);
+                        initialize_fov(&game, tcod);
+                        play_game(&mut objects, &mut game, tcod, slot);
                     }
-                    Err(_e) => {
+                    Err(SaveError::Io(ref e)) if e.kind() == std::io::ErrorKind::NotFound => {
                         msgbox("\nNo saved game to load. \n.", 24, &mut tcod.root);
                         continue;
                     }
+                    Err(SaveError::Version(_)) => {
+                        msgbox("\nThe saved game is from an incompatible version and can't be loaded. \n", 24, &mut tcod.root);
+                        continue;
+                    }
+                    Err(SaveError::Outdated(version)) => {
+                        msgbox(&format!("\nThe saved game is from an older version (save version {}, this game needs {}) and can't be loaded. \n",
+                            version, SAVE_VERSION), 24, &mut tcod.root);
+                        continue;
+                    }
+                    Err(SaveError::Corrupt(_)) => {
+                        // keep a copy, in case it can be repaired by hand before the slot is reused
+                        let backup = match backup_save(slot) {
+                            Ok(path) => format!("A copy was kept as {}.", path),
+                            Err(e) => format!("It couldn't be backed up: {}", e),
+                        };
+                        msgbox(&format!("\nThe saved game is damaged and can't be loaded. {} \n", backup), 24, &mut tcod.root);
+                        continue;
+                    }
+                    Err(e) => {
+                        msgbox(&format!("\nThe saved game couldn't be loaded: {} \n", e), 24, &mut tcod.root);
+                        continue;
+                    }
                 }
             }
             Some(2) => {
+                // options for new games
+                options_menu(&mut settings, &mut tcod.root);
+            }
+            Some(3) => {
                 // quit
                 break;
             }
@@ -327,30 +923,315 @@ fn main_menu(tcod: &mut Tcod) {
     }
 }
 
-fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
-    let save_data = serde_json::to_string(&(objects, game))?;
-    let mut file = File::create("savegame")?;
+/// let the player toggle settings until they pick something that isn't an option
+fn options_menu(settings: &mut Settings, root: &mut Root) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+    loop {
+        let choices = &[
+            format!("Play the prologue: {}", on_off(settings.play_prologue)),
+            format!("Feel your way in the dark: {}", on_off(settings.touch_sense)),
+            format!("Orthogonal movement only: {}", on_off(settings.orthogonal_movement)),
+            format!("Carry by weight: {}", on_off(settings.carry_by_weight)),
+            format!("Permadeath: {}", on_off(settings.permadeath)),
+            format!("Colors: {}", settings.palette),
+            format!("Warn before stepping into light: {}", on_off(settings.warn_light)),
+            format!("Built-in field of view: {}", on_off(settings.shadowcasting)),
+        ];
+        match menu("Options (press any other key to return)\n", choices, OPTIONS_SCREEN_WIDTH, root) {
+            Some(0) => settings.play_prologue = !settings.play_prologue,
+            Some(1) => settings.touch_sense = !settings.touch_sense,
+            Some(2) => settings.orthogonal_movement = !settings.orthogonal_movement,
+            Some(3) => settings.carry_by_weight = !settings.carry_by_weight,
+            Some(4) => settings.permadeath = !settings.permadeath,
+            Some(5) => settings.palette = settings.palette.next(),
+            Some(6) => settings.warn_light = !settings.warn_light,
+            Some(7) => settings.shadowcasting = !settings.shadowcasting,
+            _ => break,
+        }
+    }
+}
+
+/// the file a save slot is kept in
+fn save_path(slot: usize) -> String {
+    format!("savegame_{}", slot + 1)
+}
+
+/// copy a slot's save file next to it, returning where the copy went
+fn backup_save(slot: usize) -> std::io::Result<String> {
+    let backup = format!("{}.bak", save_path(slot));
+    std::fs::copy(save_path(slot), &backup)?;
+    Ok(backup)
+}
+
+/// let the player pick a save slot, showing what's in each one
+fn slot_menu(header: &str, root: &mut Root) -> Option<usize> {
+    let slots: Vec<String> = (0..SAVE_SLOTS).map(|slot| {
+        match load_game(slot) {
+            Ok((objects, game)) => format!("Slot {}: character level {}, {}",
+                slot + 1, objects[PLAYER].level, level_name(game.dungeon_level)),
+            Err(e) => format!("Slot {}: {}", slot + 1, e.summary()),
+        }
+    }).collect();
+    menu(header, &slots, SLOT_SCREEN_WIDTH, root)
+}
+
+fn save_game(objects: &[Object], game: &Game, slot: usize) -> Result<(), SaveError> {
+    let save_file = SaveFileRef{version: SAVE_VERSION, objects: objects, game: game};
+    let save_data = serde_json::to_string(&save_file).map_err(SaveError::Serde)?;
+    let mut file = File::create(save_path(slot))?;
     file.write_all(save_data.as_bytes())?;
     Ok(())
 }
 
-fn load_game() -> Result<(Vec<Object>, Game), Box<Error>> {
+fn load_game(slot: usize) -> Result<(Vec<Object>, Game), SaveError> {
     let mut json_save_state = String::new();
-    let mut file = File::open("savegame")?;
+    let mut file = File::open(save_path(slot))?;
     file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
-    Ok(result)
+    parse_save(&json_save_state)
+}
+
+/// read a saved game back from its json, telling apart damaged and outdated saves
+fn parse_save(json_save_state: &str) -> Result<(Vec<Object>, Game), SaveError> {
+    use serde_json::error::Category;
+    let classify = |e: serde_json::Error| {
+        match e.classify() {
+            // the json itself is fine, it just doesn't describe the game we know
+            Category::Data => SaveError::Version(e),
+            Category::Syntax | Category::Eof => SaveError::Corrupt(e),
+            Category::Io => SaveError::Io(e.into()),
+        }
+    };
+
+    // check the version first; saves from before versioning have no header at all.
+    // there are no migrations yet, so anything older than the current version is rejected
+    let header = serde_json::from_str::<SaveHeader>(json_save_state).map_err(classify)?;
+    if header.version < SAVE_VERSION {
+        return Err(SaveError::Outdated(header.version));
+    }
+    let save_file = serde_json::from_str::<SaveFile>(json_save_state).map_err(classify)?;
+    Ok((save_file.objects, save_file.game))
 }
 
 /// advance to the next level
 fn next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
-    game.log.add("You take a moment to rest and recover your strength.", colors::VIOLET);
-    let heal_hp = objects[PLAYER].max_hp(game) / 2;
-    objects[PLAYER].heal(heal_hp, game);
-
-    game.log.add("After a rare moment of peace, you descend deepter into \
-        the heart of the dungeon...", colors::RED);
-    game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
-    initialize_fov(&game.map, tcod);
+    store_level(objects, game);
+    if restore_level(game.dungeon_level + 1, objects, game) {
+        game.log.add(format!("You descend the familiar stairs to {}.", level_name(game.dungeon_level)),
+            colors::RED);
+    } else {
+        game.log.add("You take a moment to rest and recover your strength.", colors::VIOLET);
+        let heal_hp = objects[PLAYER].max_hp(game) / 2;
+        objects[PLAYER].heal(heal_hp, game);
+
+        game.log.add("After a rare moment of peace, you descend deepter into \
+            the heart of the dungeon...", colors::RED);
+        game.dungeon_level += 1;
+        game.map = make_level_map(objects, game.dungeon_level, game.seed, game.difficulty);
+
+        // the first time down to each level is worth some experience
+        let xp = DESCEND_XP * game.dungeon_level as i32;
+        if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+            fighter.xp += xp;
+        }
+        game.log.add(format!("You gain {} experience points for delving deeper.", xp), colors::YELLOW);
+    }
+    initialize_fov(game, tcod);
+}
+
+/// go back up to the level above, as the player left it
+fn previous_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
+    let above = match game.dungeon_level.checked_sub(1) {
+        Some(level) if game.levels.contains_key(&level) => level,
+        _ => {
+            game.log.add("The way up is blocked by rubble.", colors::LIGHT_GREY);
+            return;
+        }
+    };
+    store_level(objects, game);
+    restore_level(above, objects, game);
+    initialize_fov(game, tcod);
+    game.log.add(format!("You climb back up to {}.", level_name(game.dungeon_level)), colors::RED);
+}
+
+/// on a staircase: go down, or travel straight to any level visited before.
+/// going down from the deepest level of the run wins it
+fn take_stairs(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) -> PlayerAction {
+    let at_bottom = game.max_depth.map_or(false, |depth| game.dungeon_level >= depth);
+    if game.levels.is_empty() && !at_bottom {
+        next_level(tcod, objects, game);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    // the next level down, then every level visited so far (the menu only fits 26)
+    let next = game.dungeon_level + 1;
+    let mut destinations = vec![next];
+    destinations.extend(game.levels.keys().cloned().filter(|&level| level != next));
+    destinations.truncate(26);
+    let options = destinations.iter().map(|&level| {
+        if level == next && at_bottom {
+            "Leave the dungeon, ending the run".to_string()
+        } else if level == next {
+            format!("Descend to {}", level_name(level))
+        } else {
+            format!("Travel to {}", level_name(level))
+        }
+    }).collect::<Vec<_>>();
+
+    match menu("Where do you want to go?\n", &options, LEVEL_SCREEN_WIDTH, &mut tcod.root) {
+        Some(0) if at_bottom => return PlayerAction::Won,
+        Some(0) => next_level(tcod, objects, game),
+        Some(index) => travel_to_level(destinations[index], tcod, objects, game),
+        None => {}
+    }
+    PlayerAction::DidntTakeTurn
+}
+
+/// skip straight to a level visited before, arriving on the stairs the player left by
+fn travel_to_level(dungeon_level: u32, tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
+    if !game.levels.contains_key(&dungeon_level) {
+        game.log.add("You don't know the way there.", colors::RED);
+        return;
+    }
+    let from = game.dungeon_level;
+    store_level(objects, game);
+    restore_level(dungeon_level, objects, game);
+    initialize_fov(game, tcod);
+    game.log.add(format!("You make the long journey from {} to {}.", level_name(from), level_name(dungeon_level)),
+        colors::LIGHT_VIOLET);
+}
+
+/// put the current level away, so it can be returned to later
+fn store_level(objects: &mut Vec<Object>, game: &mut Game) {
+    let player_pos = objects[PLAYER].pos();
+    let level = Level {
+        map: std::mem::replace(&mut game.map, vec![]),
+        objects: objects.drain(1..).collect(), // everything but the player
+        player_pos: player_pos,
+    };
+    game.levels.insert(game.dungeon_level, level);
+}
+
+/// bring back a level visited before, with the player where they left it.
+/// returns false if the level hasn't been visited
+fn restore_level(dungeon_level: u32, objects: &mut Vec<Object>, game: &mut Game) -> bool {
+    match game.levels.remove(&dungeon_level) {
+        Some(level) => {
+            game.map = level.map;
+            objects.truncate(1);
+            objects.extend(level.objects);
+            objects[PLAYER].set_pos(level.player_pos.0, level.player_pos.1);
+            game.dungeon_level = dungeon_level;
+            true
+        }
+        None => false,
+    }
+}
+
+/// how a dungeon level is called in messages
+fn level_name(dungeon_level: u32) -> String {
+    match dungeon_level {
+        0 => "the prologue".to_string(),
+        level => format!("level {}", level),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_saved_game_loads_back_the_same() {
+        let mut objects = vec![new_player()];
+        objects[PLAYER].set_pos(2, 3);
+        objects.push(make_monster("orc", 4, 5, 1));
+        let map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut game = Game::new(map, Settings::new(), 1234, Difficulty::Hard, Some(12));
+        game.dungeon_level = 3;
+        game.gold = 42;
+        game.log.add("Welcome back.", colors::WHITE);
+
+        let save_file = SaveFileRef{version: SAVE_VERSION, objects: &objects, game: &game};
+        let json = serde_json::to_string(&save_file).unwrap();
+        let (loaded_objects, loaded_game) = parse_save(&json).unwrap();
+
+        assert_eq!(loaded_objects.len(), 2);
+        assert_eq!(loaded_objects[PLAYER].pos(), (2, 3));
+        assert_eq!(loaded_objects[1].name, "orc");
+        assert_eq!(loaded_objects[1].pos(), (4, 5));
+        assert_eq!(loaded_game.seed, 1234);
+        assert_eq!(loaded_game.dungeon_level, 3);
+        assert_eq!(loaded_game.gold, 42);
+        assert_eq!(loaded_game.difficulty, Difficulty::Hard);
+        assert_eq!(loaded_game.max_depth, Some(12));
+        assert_eq!(loaded_game.log[0].0, "Welcome back.");
+        assert_eq!(loaded_game.map.len(), MAP_WIDTH as usize);
+    }
+
+    // what play_game does with the player's action, short of drawing anything
+    fn act(player_action: PlayerAction, game: &mut Game, objects: &mut Vec<Object>) {
+        if world_moves_on(player_action, objects) {
+            let fov = helper::tests::player_view(game, objects);
+            simulate_turn(game, objects, &fov);
+        }
+    }
+
+    #[test]
+    fn waiting_and_moving_count_as_turns() {
+        let mut game = helper::tests::game_with_map(&["#####", "#...#", "#####"]);
+        let mut objects = vec![new_player()];
+        objects[PLAYER].set_pos(1, 1);
+
+        // waiting always takes a turn
+        act(PlayerAction::TookTurn, &mut game, &mut objects);
+        assert_eq!(game.turn_count, 1);
+
+        assert!(player_move_or_attack(1, 0, false, &mut game, &mut objects));
+        act(PlayerAction::TookTurn, &mut game, &mut objects);
+        assert_eq!(game.turn_count, 2);
+    }
+
+    #[test]
+    fn free_actions_dont_count_as_turns() {
+        let mut game = helper::tests::game_with_map(&["#####", "#...#", "#####"]);
+        game.map[2][1].lit = true;
+        let mut objects = vec![new_player()];
+        objects[PLAYER].set_pos(1, 1);
+
+        // the first step towards the light only warns, and isn't a turn
+        assert!(!player_move_or_attack(1, 0, false, &mut game, &mut objects));
+        for &action in &[PlayerAction::DidntTakeTurn, PlayerAction::Exit] {
+            act(action, &mut game, &mut objects);
+        }
+        assert_eq!(game.turn_count, 0);
+
+        // nor does anything once the player is dead
+        objects[PLAYER].alive = false;
+        act(PlayerAction::TookTurn, &mut game, &mut objects);
+        assert_eq!(game.turn_count, 0);
+    }
+
+    #[test]
+    fn a_cut_off_save_is_corrupt() {
+        assert!(matches!(parse_save("{\"version\":"), Err(SaveError::Corrupt(_))));
+        assert!(matches!(parse_save("not json"), Err(SaveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn a_save_without_a_version_is_from_before_versioning() {
+        assert!(matches!(parse_save("{\"objects\":[]}"), Err(SaveError::Version(_))));
+    }
+
+    #[test]
+    fn an_older_save_is_outdated_and_says_its_version() {
+        match parse_save("{\"version\":1}") {
+            Err(SaveError::Outdated(version)) => assert_eq!(version, 1),
+            other => panic!("expected an outdated save, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn a_current_save_that_doesnt_fit_the_game_is_a_version_mismatch() {
+        let json = format!("{{\"version\":{},\"objects\":[],\"game\":{{}}}}", SAVE_VERSION);
+        assert!(matches!(parse_save(&json), Err(SaveError::Version(_))));
+    }
 }
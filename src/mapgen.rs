@@ -4,36 +4,85 @@ use crate::user_defined::*;
 use crate::helper::*;
 
 use std::cmp;
-use tcod::colors::{self};
-use rand::Rng;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::fs::File;
+use tcod::colors::{self, Color};
+use rand::{Rng, SeedableRng, StdRng};
 use rand::distributions::{Weighted, WeightedChoice, IndependentSample};
 
-pub fn make_map_debug(objects: &mut Vec<Object>, level: u32) -> Map {
-    let mut map = vec![vec![Tile::empty(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+// prefab rooms ("vaults"), sometimes placed instead of a plain room. each is drawn
+// with '#' wall, '.' floor, 'm' a monster for the level, '!' an item for the level
+// and 'i' a torch. they can be rotated, and tunnels join them at the center tile,
+// so it must not be a wall
+const VAULTS: &[&str] = &[
+"#########
+#.......#
+#.##.##.#
+#.#m!m#.#
+#...i...#
+#.#m!m#.#
+#.##.##.#
+#.......#
+#########",
+"###########
+#m.......m#
+#.#######.#
+#.#!.i.!#.#
+#.###.###.#
+#....m....#
+###########",
+];
 
-    // player is the first element, remove everything else.
-    // NOTE: works only when the player is the first object!
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
+/// build a hand-authored level from a layout file. each character is one tile:
+/// '#' wall, '.' floor, '@' player start, 'i' torch, 'r' rat, '<' stairs.
+/// anything outside the layout is filled with wall
+pub fn make_map_from_file(path: &str, objects: &mut Vec<Object>, rng: &mut StdRng) -> Result<Map, MapFileError> {
+    let mut layout = String::new();
+    let mut file = File::open(path)?;
+    file.read_to_string(&mut layout)?;
+
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
 
     // player is the first element, remove everything else.
     // NOTE: works only when the player is the first object!
     assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
     objects.truncate(1);
 
-    let player = &mut objects[PLAYER];
-    player.set_pos(20, 20);
-
-    let mut torch = Object::new(15, 15, 'i', "torch", colors::ORANGE, false);
-    torch.emitter = Some(Emitter{radius: 2, color: colors::ORANGE});
-    torch.always_visible = true;
-    objects.push(torch);
+    for (y, line) in layout.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let (x, y) = (x as i32, y as i32);
+            if x >= MAP_WIDTH || y >= MAP_HEIGHT {
+                return Err(MapFileError::TooLarge);
+            }
+            // everything but a wall can be walked on
+            if c != '#' {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+            match c {
+                '#' | '.' => {},
+                '@' => objects[PLAYER].set_pos(x, y),
+                'i' => objects.push(make_torch(x, y, 0, rng)),
+                'r' => {
+                    // a weak monster, so the player can learn to fight in the dark safely
+                    let mut rat = make_monster("rat", x, y, 0);
+                    rat.alive = true;
+                    objects.push(rat);
+                },
+                '<' => {
+                    let mut stairs = Object::new(x, y, '<', "stairs", colors::WHITE, false);
+                    stairs.always_visible = true;
+                    objects.push(stairs);
+                },
+                _ => return Err(MapFileError::UnknownTile(c)),
+            }
+        }
+    }
 
-    // return the map
-    map
+    Ok(map)
 }
 
-pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
+pub fn make_map(objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, rng: &mut StdRng) -> Map {
     // fill map with "unblocked" tiles
     let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
 
@@ -43,14 +92,29 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
     objects.truncate(1);
 
     let mut rooms = vec![];
+    let mut vault_room = None; // index into rooms, stocked by its template rather than place_objects
 
     for _ in 0..MAX_ROOMS {
+        // now and then, try a vault instead (never as the first room, where the player starts)
+        let vault = if !rooms.is_empty() && vault_room.is_none() && rng.gen_range(0, 100) < VAULT_CHANCE {
+            let template = VAULTS[rng.gen_range(0, VAULTS.len())];
+            parse_vault(template, rng.gen_range(0, 4))
+        } else {
+            None
+        };
+
         // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+        let (w, h) = match vault {
+            // the template includes its own walls, which a plain room's rect leaves off
+            Some(ref grid) => (grid.len() as i32 - 1, grid[0].len() as i32 - 1),
+            None => (rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1), rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1)),
+        };
+        if w >= MAP_WIDTH || h >= MAP_HEIGHT {
+            continue;
+        }
         // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, MAP_HEIGHT - h);
+        let x = rng.gen_range(0, MAP_WIDTH - w);
+        let y = rng.gen_range(0, MAP_HEIGHT - h);
 
         let new_room = Rect::new(x, y, w, h);
 
@@ -61,10 +125,13 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
             // this means there are no intersections, so this room is valid
             
             // paint it to the map's tiles
-            create_room(new_room, &mut map);
-
-            // add some content to this room, such as monsters
-            place_objects(new_room, &map, objects, level);
+            match vault {
+                Some(ref grid) => {
+                    place_vault(grid, x, y, &mut map, objects, level, difficulty, rng);
+                    vault_room = Some(rooms.len());
+                },
+                None => create_room(new_room, &mut map),
+            }
 
             // center coordinates of the new room, will be useful later
             let (new_x, new_y) = new_room.center();
@@ -83,7 +150,7 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
                 let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
 
                 // draw a coin (random bool value -- either true or false)
-                if rand::random() {
+                if rng.gen() {
                     // first move horizontally, then vertically
                     create_h_tunnel(prev_x, new_x, prev_y, &mut map);
                     create_v_tunnel(prev_y, new_y, new_x, &mut map);
@@ -98,16 +165,349 @@ pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
         }
     }
 
+    // decide how much goes in each room, then add the content, such as monsters.
+    // a vault already has its own
+    let stocked: Vec<Rect> = rooms.iter().enumerate()
+        .filter(|&(index, _)| Some(index) != vault_room)
+        .map(|(_, room)| *room)
+        .collect();
+    let budgets = plan_room_budgets(stocked.len(), level, difficulty, rng);
+    for (room, budget) in stocked.iter().zip(budgets) {
+        place_objects(*room, budget, &map, objects, level, difficulty, rng);
+    }
+    light_start_room(rooms[0], &map, objects, level, rng);
+
+    // some rooms (never the player's) get a pool of water, or lava deeper down
+    for room in stocked.iter().skip(1) {
+        if rng.gen_range(0, 100) < POOL_CHANCE {
+            paint_pool(*room, &mut map, objects, level, rng);
+        }
+    }
+
     // create stairs at the center of thee last room
     let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
     let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", colors::WHITE, false);
     stairs.always_visible = true;
     objects.push(stairs);
 
+    // the rooms should all be joined up, but make sure the way down can't be cut off
+    let start = objects[PLAYER].pos();
+    ensure_reachable(start, (last_room_x, last_room_y), &mut map);
+
+    // and stairs back up where the player arrives
+    objects.push(make_up_stairs(objects[PLAYER].x, objects[PLAYER].y));
+
     // return the map and starting position
     map
 }
 
+/// generate the map for a new dungeon level, picking the generator by depth:
+/// from CAVE_MIN_LEVEL down, every other level is a cave.
+/// the same seed and level always make the same map
+pub fn make_level_map(objects: &mut Vec<Object>, level: u32, seed: u32, difficulty: Difficulty) -> Map {
+    let mut rng = level_rng(seed, level);
+    let map = if level >= CAVE_MIN_LEVEL && (level - CAVE_MIN_LEVEL) % 2 == 0 {
+        make_cave_map(objects, level, difficulty, &mut rng)
+    } else {
+        make_map(objects, level, difficulty, &mut rng)
+    };
+    if level % SHOP_LEVEL_INTERVAL == 0 {
+        place_shopkeeper(&map, objects, level, difficulty, &mut rng);
+    }
+
+    for monster in objects[1..].iter_mut().filter(|object| object.faction == Faction::Hostile) {
+        scale_monster(monster, level, difficulty);
+    }
+    map
+}
+
+/// a monster hits harder and takes more punishment on harder difficulties, and keeps
+/// getting tougher past the usual deepest level, for endless runs
+pub fn scale_monster(monster: &mut Object, level: u32, difficulty: Difficulty) {
+    let depth_scale = 1.0 + ENDLESS_SCALE_PER_LEVEL * level.saturating_sub(FINAL_DUNGEON_LEVEL) as f32;
+    if let Some(ref mut fighter) = monster.fighter {
+        fighter.base_max_hp = (difficulty.scale(fighter.base_max_hp as u32) as f32 * depth_scale).round() as i32;
+        fighter.hp = fighter.base_max_hp;
+        fighter.base_power = (difficulty.scale(fighter.base_power as u32) as f32 * depth_scale).round() as i32;
+    }
+}
+
+/// set up a shopkeeper on a free floor tile, stocked with items for the level
+fn place_shopkeeper(map: &Map, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, rng: &mut StdRng) {
+    // stay out of corridors, where the shopkeeper would be in the way
+    let floor = reachable_from(objects[PLAYER].x, objects[PLAYER].y, map);
+    let free: Vec<&(i32, i32)> = floor.iter()
+        .filter(|&&(x, y)| count_adjacent_walls(x, y, map) == 0)
+        .filter(|&&(x, y)| !objects.iter().any(|object| object.pos() == (x, y)))
+        .collect();
+    if free.is_empty() {
+        return;
+    }
+    let (x, y) = *free[rng.gen_range(0, free.len())];
+
+    // sturdy, and minds its own business unless someone takes its stock by force
+    let mut shopkeeper = Object::new(x, y, '@', "shopkeeper", colors::GOLD, true);
+    shopkeeper.alive = true;
+    shopkeeper.fighter = Some(Fighter{base_max_hp: 60, hp: 60, base_defense: 4, base_power: 8,
+        on_death: DeathCallback::Monster, xp: 0});
+    for _ in 0..SHOP_STOCK {
+        shopkeeper.inventory.push(make_item(random_item(level, difficulty, rng), x, y));
+    }
+    objects.push(shopkeeper);
+}
+
+/// the random numbers for generating one level of a seeded game. each level gets
+/// its own, so a level comes out the same no matter what happened before it
+pub fn level_rng(seed: u32, level: u32) -> StdRng {
+    StdRng::from_seed(&[seed as usize, level as usize][..])
+}
+
+/// carve organic caverns with a cellular automaton, then join up any pockets
+/// it left so the whole cave can be walked
+pub fn make_cave_map(objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, rng: &mut StdRng) -> Map {
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+    // player is the first element, remove everything else.
+    // NOTE: works only when the player is the first object!
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    // random noise, with a solid border
+    for x in 1..(MAP_WIDTH - 1) {
+        for y in 1..(MAP_HEIGHT - 1) {
+            if rng.gen_range(0, 100) >= CAVE_WALL_CHANCE {
+                map[x as usize][y as usize] = Tile::empty();
+            }
+        }
+    }
+
+    // smooth it out: crowded tiles fill in, lonely walls crumble away
+    for _ in 0..CAVE_SMOOTHING_STEPS {
+        let mut next = map.clone();
+        for x in 1..(MAP_WIDTH - 1) {
+            for y in 1..(MAP_HEIGHT - 1) {
+                let walls = count_adjacent_walls(x, y, &map);
+                if walls >= 5 {
+                    next[x as usize][y as usize] = Tile::wall();
+                } else if walls <= 3 {
+                    next[x as usize][y as usize] = Tile::empty();
+                }
+            }
+        }
+        map = next;
+    }
+
+    // find the separate pockets of floor and tunnel each one into the biggest
+    let mut regions = floor_regions(&map);
+    if regions.is_empty() {
+        // nothing survived the smoothing, so make a room to stand in
+        create_room(Rect::new(MAP_WIDTH / 2 - 4, MAP_HEIGHT / 2 - 4, 8, 8), &mut map);
+        regions = floor_regions(&map);
+    }
+    regions.sort_by_key(|region| cmp::Reverse(region.len()));
+    let main = regions[0][0];
+    for region in &regions[1..] {
+        let (x, y) = region[0];
+        create_h_tunnel(x, main.0, y, &mut map);
+        create_v_tunnel(y, main.1, main.0, &mut map);
+    }
+
+    // the player starts somewhere random, and the stairs go as far away as the cave allows
+    let floor = floor_regions(&map).remove(0);
+    let (start_x, start_y) = floor[rng.gen_range(0, floor.len())];
+    objects[PLAYER].set_pos(start_x, start_y);
+    let (stairs_x, stairs_y) = *reachable_from(start_x, start_y, &map).last().unwrap();
+
+    // stock the cave a square at a time, starting with the player's square so it stays safe
+    let mut areas = vec![];
+    for x in (0..MAP_WIDTH).step_by(CAVE_AREA_SIZE as usize) {
+        for y in (0..MAP_HEIGHT).step_by(CAVE_AREA_SIZE as usize) {
+            let area = Rect::new(x, y,
+                cmp::min(CAVE_AREA_SIZE, MAP_WIDTH - 1 - x), cmp::min(CAVE_AREA_SIZE, MAP_HEIGHT - 1 - y));
+            let has_floor = ((area.x1 + 1)..area.x2).any(|x| {
+                ((area.y1 + 1)..area.y2).any(|y| !map[x as usize][y as usize].blocked)
+            });
+            if has_floor {
+                areas.push(area);
+            }
+        }
+    }
+    let start_area = areas.iter().position(|area| {
+        start_x > area.x1 && start_x < area.x2 && start_y > area.y1 && start_y < area.y2
+    });
+    if let Some(index) = start_area {
+        areas.swap(0, index);
+    }
+    let budgets = plan_room_budgets(areas.len(), level, difficulty, rng);
+    for (area, budget) in areas.iter().zip(budgets) {
+        place_objects(*area, budget, &map, objects, level, difficulty, rng);
+    }
+
+    // a cave is never completely dark
+    if !objects.iter().any(|object| object.name == "torch" && object.emitter.is_some()) {
+        let (x, y) = floor[rng.gen_range(0, floor.len())];
+        objects.push(make_torch(x, y, level, rng));
+    }
+
+    let mut stairs = Object::new(stairs_x, stairs_y, '<', "stairs", colors::WHITE, false);
+    stairs.always_visible = true;
+    objects.push(stairs);
+    objects.push(make_up_stairs(start_x, start_y));
+
+    map
+}
+
+/// flood part of a room with a pool of water, or maybe lava from LAVA_MIN_LEVEL down.
+/// the room's center stays dry, since the tunnels and stairs meet there, and so
+/// does anything already placed in the room
+fn paint_pool(room: Rect, map: &mut Map, objects: &mut Vec<Object>, level: u32, rng: &mut StdRng) {
+    let lava = level >= LAVA_MIN_LEVEL && rng.gen();
+    let center_x = rng.gen_range(room.x1 + 1, room.x2);
+    let center_y = rng.gen_range(room.y1 + 1, room.y2);
+    let radius: i32 = rng.gen_range(1, 3);
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            let in_pool = (x - center_x).pow(2) + (y - center_y).pow(2) <= radius.pow(2);
+            let occupied = (x, y) == room.center() || objects.iter().any(|object| object.pos() == (x, y));
+            if in_pool && !occupied {
+                map[x as usize][y as usize] = if lava { Tile::lava() } else { Tile::water() };
+            }
+        }
+    }
+
+    // lava gives off a dull glow
+    if lava && map[center_x as usize][center_y as usize].terrain == Terrain::Lava {
+        let mut glow = Object::new(center_x, center_y, '~', "lava", colors::FLAME, false);
+        glow.emitter = Some(Emitter{radius: LAVA_GLOW_RADIUS, color: colors::FLAME, fuel: i32::MAX});
+        glow.always_visible = true;
+        objects.push(glow);
+    }
+}
+
+/// read a vault template into a grid of characters indexed [x][y] like the map,
+/// turned a quarter clockwise the given number of times. returns None if the
+/// template isn't a rectangle, or its center is a wall
+fn parse_vault(template: &str, quarter_turns: u32) -> Option<Vec<Vec<char>>> {
+    let rows: Vec<Vec<char>> = template.lines().map(|line| line.chars().collect()).collect();
+    let width = rows.first().map_or(0, |row| row.len());
+    if width == 0 || rows.iter().any(|row| row.len() != width) {
+        return None;
+    }
+    let mut grid: Vec<Vec<char>> = (0..width).map(|x| rows.iter().map(|row| row[x]).collect()).collect();
+    for _ in 0..quarter_turns {
+        let height = grid[0].len();
+        grid = (0..height).map(|x| (0..grid.len()).map(|y| grid[y][height - 1 - x]).collect()).collect();
+    }
+    if grid[grid.len() / 2][grid[0].len() / 2] == '#' {
+        return None;
+    }
+    Some(grid)
+}
+
+/// paint a parsed vault onto the map with its top-left corner at (x, y), and add what's drawn in it
+fn place_vault(grid: &[Vec<char>], x: i32, y: i32, map: &mut Map, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, rng: &mut StdRng) {
+    for (dx, column) in grid.iter().enumerate() {
+        for (dy, &tile) in column.iter().enumerate() {
+            let (x, y) = (x + dx as i32, y + dy as i32);
+            map[x as usize][y as usize] = if tile == '#' { Tile::wall() } else { Tile::empty() };
+            match tile {
+                'm' => {
+                    let mut monster = make_monster(random_monster(level, difficulty, rng), x, y, level);
+                    monster.alive = true;
+                    objects.push(monster);
+                },
+                '!' => objects.push(maybe_curse(make_item(random_item(level, difficulty, rng), x, y), rng)),
+                'i' => objects.push(make_torch(x, y, level, rng)),
+                _ => {},
+            }
+        }
+    }
+}
+
+/// how many of the 8 tiles around this one are walls
+fn count_adjacent_walls(x: i32, y: i32, map: &Map) -> u32 {
+    let mut walls = 0;
+    for dx in -1..2 {
+        for dy in -1..2 {
+            if (dx, dy) != (0, 0) && map[(x + dx) as usize][(y + dy) as usize].blocked {
+                walls += 1;
+            }
+        }
+    }
+    walls
+}
+
+/// every floor tile that can be walked to from this one, nearest first.
+/// only orthogonal steps count, so it holds with orthogonal movement too
+pub fn reachable_from(x: i32, y: i32, map: &Map) -> Vec<(i32, i32)> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut reached = vec![];
+    let mut frontier = VecDeque::new();
+    seen[x as usize][y as usize] = true;
+    frontier.push_back((x, y));
+    while let Some((x, y)) = frontier.pop_front() {
+        reached.push((x, y));
+        for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= MAP_WIDTH || ny >= MAP_HEIGHT {
+                continue;
+            }
+            if !seen[nx as usize][ny as usize] && !map[nx as usize][ny as usize].blocked {
+                seen[nx as usize][ny as usize] = true;
+                frontier.push_back((nx, ny));
+            }
+        }
+    }
+    reached
+}
+
+/// carve a corridor from one tile to another if they can't already be walked between.
+/// returns true if a corridor was needed
+pub fn ensure_reachable(from: (i32, i32), to: (i32, i32), map: &mut Map) -> bool {
+    if reachable_from(from.0, from.1, map).contains(&to) {
+        return false;
+    }
+    create_h_tunnel(from.0, to.0, from.1, map);
+    create_v_tunnel(from.1, to.1, to.0, map);
+    true
+}
+
+/// split the floor into pockets that can't be walked between
+fn floor_regions(map: &Map) -> Vec<Vec<(i32, i32)>> {
+    let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut regions = vec![];
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            if !seen[x as usize][y as usize] && !map[x as usize][y as usize].blocked {
+                let region = reachable_from(x, y, map);
+                for &(rx, ry) in &region {
+                    seen[rx as usize][ry as usize] = true;
+                }
+                regions.push(region);
+            }
+        }
+    }
+    regions
+}
+
+/// a hidden trap, shown as '^' once it's found
+fn make_trap(x: i32, y: i32, kind: TrapKind) -> Object {
+    let (name, color) = match kind {
+        TrapKind::Spike => ("spike trap", colors::LIGHT_GREY),
+        TrapKind::PoisonDart => ("poison dart trap", colors::LIGHT_GREEN),
+    };
+    let mut trap = Object::new(x, y, '^', name, color, false);
+    trap.trap = Some(Trap{kind: kind, detected: false});
+    trap
+}
+
+/// the staircase leading back to the level above
+pub fn make_up_stairs(x: i32, y: i32) -> Object {
+    let mut stairs = Object::new(x, y, '>', "up stairs", colors::WHITE, false);
+    stairs.always_visible = true;
+    stairs
+}
+
 fn create_room(room: Rect, map: &mut Map) {
     for x in (room.x1 + 1)..room.x2 {
         for y in (room.y1 + 1)..room.y2 {
@@ -116,143 +516,586 @@ fn create_room(room: Rect, map: &mut Map) {
     }
 }
 
-/// take a room and add objects to it (monsters, items, etc)
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
-
-    let max_monsters = from_dungeon_level(&[
+/// share out the level's monsters and items between its rooms. some rooms are
+/// left without monsters as a breather, and a few danger rooms get a bigger share.
+/// the first room, where the player starts, is always safe
+fn plan_room_budgets(num_rooms: usize, level: u32, difficulty: Difficulty, rng: &mut StdRng) -> Vec<RoomBudget> {
+    // the whole level's budget is what the rooms would have rolled on average
+    // if each had picked a random number up to its maximum
+    let monster_budget = num_rooms as u32 * from_dungeon_level(&[
         Transition {level: 1, value: 2},
         Transition {level: 4, value: 3},
         Transition {level: 6, value: 5},
-    ], level);
+    ], level) / 2;
+    let monster_budget = difficulty.scale(monster_budget);
+    let item_budget = num_rooms as u32 * from_dungeon_level(&[
+        Transition {level: 1, value: 1},
+        Transition {level: 4, value: 2},
+    ], level) / 2;
+
+    // how big a share each room gets: nothing for safe rooms, more for danger rooms
+    let mut monster_shares = vec![];
+    let mut item_shares = vec![];
+    for index in 0..num_rooms {
+        let roll = rng.gen_range(0, 100);
+        let (monster_share, item_share) = if index == 0 || roll < SAFE_ROOM_CHANCE {
+            (0, 1)
+        } else if roll < SAFE_ROOM_CHANCE + DANGER_ROOM_CHANCE {
+            (DANGER_ROOM_SHARE, DANGER_ROOM_SHARE)
+        } else {
+            (1, 1)
+        };
+        monster_shares.push(monster_share);
+        item_shares.push(item_share);
+    }
 
-    // choose a random number of monsters
-    let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
+    let mut budgets = vec![RoomBudget{monsters: 0, items: 0}; num_rooms];
+    for room in pick_rooms(&monster_shares, monster_budget, rng) {
+        budgets[room].monsters += 1;
+    }
+    for room in pick_rooms(&item_shares, item_budget, rng) {
+        budgets[room].items += 1;
+    }
+    budgets
+}
 
-    // monster random table
+/// pick a room (by index) for each of `count` things, weighted by each room's share
+fn pick_rooms(shares: &[u32], count: u32, rng: &mut StdRng) -> Vec<usize> {
+    let total: u32 = shares.iter().sum();
+    if total == 0 {
+        return vec![];
+    }
+    (0..count).map(|_| {
+        let mut roll = rng.gen_range(0, total);
+        shares.iter().position(|&share| {
+            if roll < share {
+                true
+            } else {
+                roll -= share;
+                false
+            }
+        }).unwrap()
+    }).collect()
+}
+
+/// create a torch fixed to the floor, with a random amount of fuel
+fn make_torch(x: i32, y: i32, level: u32, rng: &mut StdRng) -> Object {
+    let mut torch = Object::new(x, y, 'i', "torch", colors::DARKEST_ORANGE, false);
+    // deeper down, torches are smaller and have less fuel left
+    let radius = from_dungeon_level(&[Transition {level: 0, value: 3}, Transition {level: 4, value: 2}], level) as i32;
+    let fuel_scale = 1.0 / (1.0 + TORCH_FUEL_FALLOFF_PER_LEVEL * level.saturating_sub(1) as f32);
+    let fuel = (rng.gen_range(TORCH_FUEL_MIN, TORCH_FUEL_MAX + 1) as f32 * fuel_scale).round() as i32;
+    torch.emitter = Some(Emitter{radius: radius, color: colors::ORANGE, fuel: fuel});
+    torch.always_visible = true;
+    torch
+}
+
+/// the chance, out of 100, that a room has a torch in it. deeper levels are darker
+pub fn torch_chance(level: u32) -> u32 {
+    from_dungeon_level(&[
+        Transition {level: 0, value: 60},
+        Transition {level: 4, value: 40},
+        Transition {level: 7, value: 25},
+        Transition {level: 10, value: 15},
+    ], level)
+}
+
+/// how dark a dungeon level is, going by how many of its rooms have torches
+pub fn darkness(level: u32) -> &'static str {
+    match torch_chance(level) {
+        chance if chance >= 60 => "dim",
+        chance if chance >= 40 => "dark",
+        chance if chance >= 25 => "very dark",
+        _ => "pitch black",
+    }
+}
+
+/// put a torch in the room the player arrives in, on the first few levels, unless there's one already
+fn light_start_room(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32, rng: &mut StdRng) {
+    let lit = objects.iter().any(|object| {
+        object.emitter.is_some() && object.x > room.x1 && object.x < room.x2 && object.y > room.y1 && object.y < room.y2
+    });
+    if level == 0 || level > START_TORCH_LEVELS || lit {
+        return;
+    }
+    // a few tries at finding a free spot, so a crowded room doesn't hold things up
+    for _ in 0..10 {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if !is_blocked(x, y, map, objects) {
+            objects.push(make_torch(x, y, level, rng));
+            return;
+        }
+    }
+}
+
+/// create a monster by name, ready to be placed at the given position on a dungeon level
+pub fn make_monster(name: &str, x: i32, y: i32, level: u32) -> Object {
+    let mut monster = match name {
+        // tougher, tinted versions of the usual monsters, found deeper down
+        "orc veteran" => make_variant("orc", name, colors::DARK_AMBER, x, y, level),
+        "dire troll" => make_variant("troll", name, colors::DARK_CRIMSON, x, y, level),
+        "orc" => {
+            let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
+            orc.fighter = Some(Fighter{base_max_hp: 20, hp: 20, base_defense: 0, base_power: 4, on_death: DeathCallback::Monster, xp: 35});
+            orc.ai = Some(Ai::pack());
+            orc.scavenger = true;
+            orc
+        },
+        "orc warlord" => {
+            // leads an orc warband
+            let mut warlord = Object::new(x, y, 'o', "orc warlord", colors::DARKER_GREEN, true);
+            warlord.fighter = Some(Fighter{base_max_hp: 30, hp: 30, base_defense: 1, base_power: 6, on_death: DeathCallback::Monster, xp: 80});
+            warlord.ai = Some(Ai::basic());
+            warlord.scavenger = true;
+            warlord
+        },
+        "troll" => {
+            let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true); // else, a troll
+            troll.fighter = Some(Fighter{base_max_hp: 30, hp: 30, base_defense: 2, base_power: 8, on_death: DeathCallback::Monster, xp: 100});
+            troll.ai = Some(Ai::basic());
+            troll
+        },
+        "imp" => {
+            // a thief: steals an item from the player and runs off with it
+            let mut imp = Object::new(x, y, 'I', "imp", colors::LIGHT_PURPLE, true);
+            imp.fighter = Some(Fighter{base_max_hp: 10, hp: 10, base_defense: 0, base_power: 2, on_death: DeathCallback::Monster, xp: 40});
+            imp.ai = Some(Ai::Thief);
+            imp
+        },
+        "will-o-wisp" => {
+            // carries its own light, so it can be seen coming from across a dark room
+            let mut wisp = Object::new(x, y, 'w', "will-o-wisp", colors::LIGHT_CYAN, true);
+            wisp.fighter = Some(Fighter{base_max_hp: 8, hp: 8, base_defense: 0, base_power: 3, on_death: DeathCallback::Monster, xp: 30});
+            wisp.ai = Some(Ai::basic());
+            wisp.emitter = Some(Emitter{radius: 2, color: colors::LIGHT_CYAN, fuel: i32::MAX}); // never burns out
+            // there's no mind to confuse or body to freeze
+            wisp.immunities = vec![Immunity::Confusion, Immunity::Freeze];
+            wisp
+        },
+        "giant spider" => {
+            // a weak bite, but a venomous one
+            let mut spider = Object::new(x, y, 's', "giant spider", colors::DARK_SEPIA, true);
+            spider.fighter = Some(Fighter{base_max_hp: 12, hp: 12, base_defense: 0, base_power: 3, on_death: DeathCallback::Monster, xp: 45});
+            spider.ai = Some(Ai::basic());
+            spider.on_hit = Some(StatusEffect{kind: StatusKind::Poison, turns_left: SPIDER_POISON_TURNS, power: SPIDER_POISON_DAMAGE});
+            spider
+        },
+        "goblin archer" => {
+            // fragile, but shoots from across the room
+            let mut archer = Object::new(x, y, 'g', "goblin archer", colors::DARK_YELLOW, true);
+            archer.fighter = Some(Fighter{base_max_hp: 14, hp: 14, base_defense: 0, base_power: 5, on_death: DeathCallback::Monster, xp: 60});
+            archer.ai = Some(Ai::Ranged);
+            archer
+        },
+        "goblin shaman" => {
+            // stays back and lets its rats do the fighting
+            let mut shaman = Object::new(x, y, 'g', "goblin shaman", colors::DARK_VIOLET, true);
+            shaman.fighter = Some(Fighter{base_max_hp: 16, hp: 16, base_defense: 0, base_power: 3, on_death: DeathCallback::Monster, xp: 70});
+            shaman.ai = Some(Ai::Summoner{cooldown: 0});
+            shaman
+        },
+        "rat" => {
+            let mut rat = Object::new(x, y, 'r', "rat", colors::LIGHT_SEPIA, true);
+            rat.fighter = Some(Fighter{base_max_hp: 6, hp: 6, base_defense: 0, base_power: 2, on_death: DeathCallback::Monster, xp: 10});
+            rat.ai = Some(Ai::basic());
+            rat
+        },
+        _ => unreachable!(),
+    };
+    // a monster sees by its own sight radius; the sharp-eyed and the dim-witted differ from the rest
+    monster.fov_radius = match name {
+        "goblin archer" => MONSTER_SIGHT_RANGE_LIT + 2.0,
+        "troll" | "dire troll" => MONSTER_SIGHT_RANGE_LIT - 3.0,
+        _ => MONSTER_SIGHT_RANGE_LIT,
+    };
+    monster.faction = Faction::Hostile;
+    monster
+}
+
+/// a monster built like its base kind, with its stats and experience boosted by how deep it's found
+fn make_variant(base: &str, name: &str, color: Color, x: i32, y: i32, level: u32) -> Object {
+    let mut monster = make_monster(base, x, y, level);
+    monster.name = name.into();
+    monster.color = color;
+    let scale = 1.0 + VARIANT_SCALE_PER_LEVEL * level as f32;
+    if let Some(fighter) = monster.fighter.as_mut() {
+        fighter.base_max_hp = (fighter.base_max_hp as f32 * scale).round() as i32;
+        fighter.hp = fighter.base_max_hp;
+        fighter.base_power = (fighter.base_power as f32 * scale).round() as i32;
+        fighter.base_defense += 1;
+        fighter.xp = (fighter.xp as f32 * scale).round() as i32;
+    }
+    monster
+}
+
+/// what each kind of monster might be carrying, as (item, percent chance)
+fn drop_table(name: &str) -> &'static [(Item, i32)] {
+    match name {
+        "orc" | "orc veteran" => &[(Item::Ration, 20), (Item::Heal, 10)],
+        "orc warlord" => &[(Item::Heal, 50), (Item::Sword, 25), (Item::Shield, 25)],
+        "troll" | "dire troll" => &[(Item::Ration, 40), (Item::Helmet, 15)],
+        "imp" => &[(Item::Confuse, 20)],
+        "will-o-wisp" => &[(Item::Light, 30)],
+        "giant spider" => &[(Item::Acid, 20)],
+        "goblin archer" => &[(Item::Arrows, 60), (Item::Bow, 10)],
+        _ => &[],
+    }
+}
+
+/// roll a new monster's drop table, putting what it gets into its inventory for its remains to hold
+fn roll_drops(monster: &mut Object, rng: &mut StdRng) {
+    for &(item, chance) in drop_table(&monster.name) {
+        if rng.gen_range(0, 100) < chance {
+            let (x, y) = monster.pos();
+            monster.inventory.push(maybe_curse(make_item(item, x, y), rng));
+        }
+    }
+}
+
+/// pick a group that's allowed at this depth and fits in the room's monster budget
+fn choose_group(level: u32, budget: u32, rng: &mut StdRng) -> Option<MonsterGroup> {
+    let groups = [
+        (MonsterGroup::OrcWarband, from_dungeon_level(&[Transition {level: 2, value: 20}], level)),
+        (MonsterGroup::TrollPair, from_dungeon_level(&[Transition {level: 5, value: 10}], level)),
+    ];
+    let mut group_chances = groups.iter()
+        .filter(|&&(group, weight)| weight > 0 && group.members().len() as u32 <= budget)
+        .map(|&(group, weight)| Weighted {weight: weight, item: group})
+        .collect::<Vec<_>>();
+    if group_chances.is_empty() {
+        return None;
+    }
+    Some(WeightedChoice::new(&mut group_chances).ind_sample(rng))
+}
+
+/// place a group of monsters clustered around a random spot in the room.
+/// returns how many of them actually fit
+fn spawn_group(group: MonsterGroup, room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32, rng: &mut StdRng) -> u32 {
+    let center_x = rng.gen_range(room.x1 + 1, room.x2);
+    let center_y = rng.gen_range(room.y1 + 1, room.y2);
+
+    let mut spawned = 0;
+    for name in group.members() {
+        // look for a free tile near the center, staying inside the room
+        for _ in 0..GROUP_PLACEMENT_TRIES {
+            let x = cmp::max(room.x1 + 1, cmp::min(room.x2 - 1, center_x + rng.gen_range(-2, 3)));
+            let y = cmp::max(room.y1 + 1, cmp::min(room.y2 - 1, center_y + rng.gen_range(-2, 3)));
+            if !is_blocked(x, y, map, objects) {
+                let mut monster = make_monster(name, x, y, level);
+                monster.alive = true;
+                roll_drops(&mut monster, rng);
+                objects.push(monster);
+                spawned += 1;
+                break;
+            }
+        }
+    }
+    spawned
+}
+
+/// pick a monster to spawn on this level, by name
+fn random_monster(level: u32, difficulty: Difficulty, rng: &mut StdRng) -> &'static str {
     let troll_chance = from_dungeon_level(&[
         Transition {level: 3, value: 15},
         Transition {level: 5, value: 30},
         Transition {level: 7, value: 60},
     ], level);
+    let troll_chance = difficulty.scale(troll_chance);
 
     // monster random table
     let monster_chances = &mut [
         Weighted {weight: 80, item: "orc"},
         Weighted {weight: troll_chance, item: "troll"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: "imp"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 10}], level), item: "will-o-wisp"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 5, value: 10}, Transition {level: 7, value: 20}], level), item: "goblin archer"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 15}], level), item: "giant spider"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 5, value: 8}], level), item: "goblin shaman"},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 10}, Transition {level: 6, value: 20}], level), item: "orc veteran"},
+        Weighted {weight: difficulty.scale(from_dungeon_level(&[Transition {level: 6, value: 10}, Transition {level: 8, value: 20}], level)), item: "dire troll"},
     ];
-    let monster_choice = WeightedChoice::new(monster_chances);
+    WeightedChoice::new(monster_chances).ind_sample(rng)
+}
+
+/// pick an item to spawn on this level
+fn random_item(level: u32, difficulty: Difficulty, rng: &mut StdRng) -> Item {
+    let item_chances = &mut [
+        // healing potion always shows up, even if all other items have 0 chance
+        Weighted {weight: difficulty.scale_down(35), item: Item::Heal},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 25}], level), item: Item::Lightning},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 6, value: 25}], level), item: Item::Fireball},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Confuse},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 5}], level), item: Item::Sword},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 8, value: 15}], level), item: Item::Shield},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 10}], level), item: Item::Berserk},
+        Weighted {weight: 15, item: Item::Torch},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 5, value: 10}], level), item: Item::Mapping},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Light},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 10}], level), item: Item::Freeze},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 5, value: 5}], level), item: Item::ConfuseBurst},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Acid},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 5}], level), item: Item::RegenRing},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 5}], level), item: Item::Bow},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Identify},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 5}], level), item: Item::RemoveCurse},
+        Weighted {weight: 20, item: Item::Ration},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 5}], level), item: Item::Helmet},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 6, value: 5}], level), item: Item::Armor},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 7, value: 3}], level), item: Item::Amulet},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Arrows},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 5}], level), item: Item::WandLightning},
+        Weighted {weight: from_dungeon_level(&[Transition {level: 3, value: 5}], level), item: Item::Dig},
+    ];
+    WeightedChoice::new(item_chances).ind_sample(rng)
+}
+
+/// some gear lying around in the dark is cursed, its bonuses turned against the wearer
+fn maybe_curse(mut item: Object, rng: &mut StdRng) -> Object {
+    if let Some(ref mut equipment) = item.equipment {
+        if rng.gen_range(0, 100) < CURSE_CHANCE {
+            equipment.cursed = true;
+            equipment.max_hp_bonus = -equipment.max_hp_bonus;
+            equipment.power_bonus = -equipment.power_bonus;
+            equipment.defense_bonus = -equipment.defense_bonus;
+            equipment.regen_bonus = -equipment.regen_bonus;
+        }
+    }
+    item
+}
+
+/// create an item lying on the floor
+fn make_item(item: Item, x: i32, y: i32) -> Object {
+    let mut item = match item {
+        Item::Heal => {
+            let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
+            object.item = Some(Item::Heal);
+            object.weight = 1;
+            object
+        },
+        Item::Lightning => {
+            let mut object = Object::new(x, y, '#', "scroll of lightning bolt", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Lightning);
+            object.weight = 1;
+            object
+        },
+        Item::Fireball => {
+            let mut object = Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Fireball);
+            object.weight = 1;
+            object
+        },
+        Item::Confuse => {
+            let mut object = Object::new(x, y, '#', "scroll of confuse", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Confuse);
+            object.weight = 1;
+            object
+        },
+        Item::Sword => {
+            // create a sword
+            let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
+            object.item  = Some(Item::Sword);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::RightHand, max_hp_bonus: 0, power_bonus: 3, defense_bonus: 0, regen_bonus: 0, cursed: false});
+            object.weight = 6;
+            object
+        },
+        Item::Shield => {
+            // create a shield
+            let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
+            object.item  = Some(Item::Shield);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::LeftHand, max_hp_bonus: 0, power_bonus: 0, defense_bonus: 1, regen_bonus: 0, cursed: false});
+            object.weight = 8;
+            object
+        },
+        Item::Berserk => {
+            let mut object = Object::new(x, y, '!', "potion of berserk rage", colors::CRIMSON, false);
+            object.item = Some(Item::Berserk);
+            object.weight = 1;
+            object
+        },
+        Item::Mapping => {
+            let mut object = Object::new(x, y, '#', "scroll of magic mapping", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Mapping);
+            object.weight = 1;
+            object
+        },
+        Item::Light => {
+            let mut object = Object::new(x, y, '#', "scroll of light", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Light);
+            object.weight = 1;
+            object
+        },
+        Item::Freeze => {
+            let mut object = Object::new(x, y, '#', "scroll of freeze", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Freeze);
+            object.weight = 1;
+            object
+        },
+        Item::ConfuseBurst => {
+            let mut object = Object::new(x, y, '#', "scroll of confusion burst", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::ConfuseBurst);
+            object.weight = 1;
+            object
+        },
+        Item::Acid => {
+            let mut object = Object::new(x, y, '!', "potion of acid", colors::LIME, false);
+            object.item = Some(Item::Acid);
+            object.weight = 1;
+            object
+        },
+        Item::RegenRing => {
+            let mut object = Object::new(x, y, '=', "ring of regeneration", colors::GOLD, false);
+            object.item = Some(Item::RegenRing);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::Finger, max_hp_bonus: 0, power_bonus: 0, defense_bonus: 0, regen_bonus: 2, cursed: false});
+            object.weight = 1;
+            object
+        },
+        Item::Bow => {
+            // shoots from a distance with the 'f' key, as long as there are arrows
+            let mut object = Object::new(x, y, '}', "bow", colors::SEPIA, false);
+            object.item = Some(Item::Bow);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::RightHand, max_hp_bonus: 0, power_bonus: 1, defense_bonus: 0, regen_bonus: 0, cursed: false});
+            object.weight = 3;
+            object
+        },
+        Item::Dig => {
+            let mut object = Object::new(x, y, '\\', "pickaxe", colors::LIGHT_GREY, false);
+            object.item = Some(Item::Dig);
+            object.charges = Some(PICKAXE_CHARGES);
+            object.weight = 4;
+            object
+        },
+        Item::WandLightning => {
+            let mut object = Object::new(x, y, '/', "wand of lightning", colors::LIGHT_BLUE, false);
+            object.item = Some(Item::WandLightning);
+            object.charges = Some(WAND_CHARGES);
+            object.weight = 1;
+            object
+        },
+        Item::Ration => {
+            let mut object = Object::new(x, y, '%', "ration", colors::DARK_ORANGE, false);
+            object.item = Some(Item::Ration);
+            object.weight = 1;
+            object
+        },
+        Item::RemoveCurse => {
+            let mut object = Object::new(x, y, '#', "scroll of remove curse", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::RemoveCurse);
+            object.weight = 1;
+            object
+        },
+        Item::Identify => {
+            let mut object = Object::new(x, y, '#', "scroll of identify", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Identify);
+            object.weight = 1;
+            object
+        },
+        Item::Helmet => {
+            let mut object = Object::new(x, y, '[', "helmet", colors::SKY, false);
+            object.item = Some(Item::Helmet);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::Head, max_hp_bonus: 0, power_bonus: 0, defense_bonus: 1, regen_bonus: 0, cursed: false});
+            object.weight = 3;
+            object
+        },
+        Item::Armor => {
+            let mut object = Object::new(x, y, '[', "chain mail", colors::SKY, false);
+            object.item = Some(Item::Armor);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::Body, max_hp_bonus: 10, power_bonus: 0, defense_bonus: 2, regen_bonus: 0, cursed: false});
+            object.weight = 10;
+            object
+        },
+        Item::Amulet => {
+            let mut object = Object::new(x, y, '"', "amulet of vigor", colors::GOLD, false);
+            object.item = Some(Item::Amulet);
+            object.equipment = Some(Equipment{equipped: false, slot: Slot::Neck, max_hp_bonus: 20, power_bonus: 1, defense_bonus: 0, regen_bonus: 0, cursed: false});
+            object.weight = 1;
+            object
+        },
+        Item::Arrows => {
+            let mut object = Object::new(x, y, ')', "bundle of arrows", colors::SEPIA, false);
+            object.item = Some(Item::Arrows);
+            object.weight = 1;
+            object
+        },
+        Item::Torch => {
+            // an unlit torch the player can carry around and light
+            let mut object = Object::new(x, y, '(', "torch", colors::SEPIA, false);
+            object.item = Some(Item::Torch);
+            object.weight = 2;
+            object
+        },
+    };
+    item.always_visible = true;
+    item
+}
+
+/// take a room and add objects to it (monsters, items, etc)
+fn place_objects(room: Rect, budget: RoomBudget, map: &Map, objects: &mut Vec<Object>, level: u32, difficulty: Difficulty, rng: &mut StdRng) {
+
+    // the number of monsters was planned for the whole level
+    let mut num_monsters = budget.monsters;
+
+    // sometimes a room's monsters arrive together, as a themed group
+    if rng.gen_range(0, 100) < GROUP_SPAWN_CHANCE {
+        if let Some(group) = choose_group(level, num_monsters, rng) {
+            let spawned = spawn_group(group, room, map, objects, level, rng);
+            num_monsters = num_monsters.saturating_sub(spawned);
+        }
+    }
+
 
     for _ in 0..num_monsters {
         // choose random spot for this monster
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
-
-        let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-            "orc" => {
-                let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                orc.fighter = Some(Fighter{base_max_hp: 20, hp: 20, base_defense: 0, base_power: 4, on_death: DeathCallback::Monster, xp: 35});
-                orc.ai = Some(Ai::Basic);
-                orc
-            },
-            "troll" => {
-                let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true); // else, a troll
-                troll.fighter = Some(Fighter{base_max_hp: 30, hp: 30, base_defense: 2, base_power: 8, on_death: DeathCallback::Monster, xp: 100});
-                troll.ai = Some(Ai::Basic);
-                troll
-            },
-            _ => unreachable!(),
-        };
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        let mut monster = make_monster(random_monster(level, difficulty, rng), x, y, level);
 
         // only place it if the tile is not blocked
         if !is_blocked(x, y, map, objects) {
             monster.alive = true;
+            roll_drops(&mut monster, rng);
+            // some monsters are caught napping
+            if rng.gen_range(0, 100) < SLEEP_CHANCE {
+                let ai_when_awake = Box::new(monster.ai.take().unwrap_or_else(Ai::basic));
+                monster.ai = Some(Ai::Sleeping{ai_when_awake: ai_when_awake});
+            }
             objects.push(monster);
         }
     }
 
-    // max number of items per room
-    let max_items = from_dungeon_level(&[
-        Transition {level: 1, value: 1},
-        Transition {level: 4, value: 2},
-    ], level);
-
-    // choose a random number of items
-    let num_items = rand::thread_rng().gen_range(0, max_items + 1);
-
-    // item random table
-    let item_chances = &mut [
-        // healing potion always shows up, even if all other items have 0 chance
-        Weighted {weight: 35, item: Item::Heal},
-        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 25}], level), item: Item::Lightning},
-        Weighted {weight: from_dungeon_level(&[Transition {level: 6, value: 25}], level), item: Item::Fireball},
-        Weighted {weight: from_dungeon_level(&[Transition {level: 2, value: 10}], level), item: Item::Confuse},
-        Weighted {weight: from_dungeon_level(&[Transition {level: 4, value: 5}], level), item: Item::Sword},
-        Weighted {weight: from_dungeon_level(&[Transition {level: 8, value: 15}], level), item: Item::Shield},
-    ];
-    let item_choice = WeightedChoice::new(item_chances);
+    // the number of items was planned for the whole level
+    let num_items = budget.items;
 
     for _ in 0..num_items {
         // choose a random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
 
         // only place it if the tile is not blocked
         if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                },
-                Item::Lightning => {
-                    let mut object = Object::new(x, y, '#', "scroll of lightning bolt", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Lightning);
-                    object
-                },
-                Item::Fireball => {
-                    let mut object = Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                },
-                Item::Confuse => {
-                    let mut object = Object::new(x, y, '#', "scroll of confuse", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Confuse);
-                    object
-                },
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
-                    object.item  = Some(Item::Sword);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::RightHand, max_hp_bonus: 0, power_bonus: 3, defense_bonus: 0});
-                    object
-                },
-                Item::Shield => {
-                    // create a shield
-                    let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
-                    object.item  = Some(Item::Shield);
-                    object.equipment = Some(Equipment{equipped: false, slot: Slot::LeftHand, max_hp_bonus: 0, power_bonus: 0, defense_bonus: 1});
-                    object
-                }
-            };
-            item.always_visible = true;
-            objects.push(item);
+            objects.push(maybe_curse(make_item(random_item(level, difficulty, rng), x, y), rng));
+        }
+    }
+
+    // now and then, a trap
+    if rng.gen_range(0, 100) < TRAP_CHANCE {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if !is_blocked(x, y, map, objects) {
+            let kind = if rng.gen() { TrapKind::Spike } else { TrapKind::PoisonDart };
+            objects.push(make_trap(x, y, kind));
         }
     }
 
-    // max number of torches per room
-    let max_torches = 1;
-    // choose a random number of torches
-    let num_torches = rand::thread_rng().gen_range(0, max_torches + 1);
-    for _ in 0..num_torches {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+    // at most one torch per room, less likely the deeper the level
+    if rng.gen_range(0, 100) < torch_chance(level) {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
 
         // only place it if the tile is not blocked
         if !is_blocked(x, y, map, objects) {
-            let mut torch = Object::new(x, y, 'i', "torch", colors::DARKEST_ORANGE, false);
-            torch.emitter = Some(Emitter{radius: 2, color: colors::DARKEST_ORANGE});
-            torch.always_visible = true;
-            objects.push(torch);
+            objects.push(make_torch(x, y, level, rng));
         }
     }
 
@@ -271,3 +1114,139 @@ fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // load a layout written out to a scratch file, with just a player to start with
+    fn load_layout(name: &str, layout: &str) -> (Result<Map, MapFileError>, Vec<Object>) {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, layout).unwrap();
+        let mut objects = vec![crate::new_player()];
+        let map = make_map_from_file(path.to_str().unwrap(), &mut objects, &mut level_rng(0, 0));
+        (map, objects)
+    }
+
+    #[test]
+    fn a_layout_file_becomes_the_map() {
+        let (map, objects) = load_layout("layout_ok.txt", "#####\n#@.<#\n#####\n");
+        let map = map.unwrap();
+        assert!(!map[1][1].blocked && !map[2][1].blocked);
+        assert!(map[0][1].blocked && map[10][10].blocked);
+        assert_eq!(objects[PLAYER].pos(), (1, 1));
+        assert!(objects.iter().any(|object| object.name == "stairs" && object.pos() == (3, 1)));
+    }
+
+    #[test]
+    fn a_layout_with_an_unknown_tile_is_rejected() {
+        match load_layout("layout_unknown.txt", "###\n#@?\n###\n").0 {
+            Err(MapFileError::UnknownTile(c)) => assert_eq!(c, '?'),
+            other => panic!("expected an unknown tile, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn a_layout_too_wide_for_the_map_is_rejected() {
+        let row = "#".repeat(MAP_WIDTH as usize + 1);
+        assert!(matches!(load_layout("layout_wide.txt", &row).0, Err(MapFileError::TooLarge)));
+    }
+
+    #[test]
+    fn a_missing_layout_file_is_an_io_error() {
+        let mut objects = vec![crate::new_player()];
+        let result = make_map_from_file("no/such/layout.txt", &mut objects, &mut level_rng(0, 0));
+        assert!(matches!(result, Err(MapFileError::Io(_))));
+    }
+
+    // what a level looks like: each tile's shape, and what's where on it
+    type Layout = (Vec<(bool, bool, Terrain)>, Vec<(String, (i32, i32))>);
+
+    fn generate(level: u32, seed: u32) -> Layout {
+        let mut objects = vec![crate::new_player()];
+        let map = make_level_map(&mut objects, level, seed, Difficulty::Normal);
+        let tiles = map.iter().flat_map(|column| column.iter())
+            .map(|tile| (tile.blocked, tile.block_sight, tile.terrain))
+            .collect();
+        let things = objects.iter().map(|object| (object.name.clone(), object.pos())).collect();
+        (tiles, things)
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_level() {
+        // a room level, a cave level and a shop level
+        for &level in &[1, CAVE_MIN_LEVEL, SHOP_LEVEL_INTERVAL * 2] {
+            assert!(generate(level, 77) == generate(level, 77), "level {} differed", level);
+        }
+    }
+
+    #[test]
+    fn a_different_seed_makes_a_different_level() {
+        assert!(generate(1, 77) != generate(1, 78));
+        assert!(generate(1, 77) != generate(2, 77));
+    }
+
+    #[test]
+    fn ensure_reachable_joins_two_sealed_rooms() {
+        let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let (left, right) = (Rect::new(2, 2, 6, 5), Rect::new(30, 20, 8, 6));
+        create_room(left, &mut map);
+        create_room(right, &mut map);
+        let (from, to) = (left.center(), right.center());
+        assert!(!reachable_from(from.0, from.1, &map).contains(&to));
+
+        assert!(ensure_reachable(from, to, &mut map));
+        let reached = reachable_from(from.0, from.1, &map);
+        for room in &[left, right] {
+            for x in (room.x1 + 1)..room.x2 {
+                for y in (room.y1 + 1)..room.y2 {
+                    assert!(reached.contains(&(x, y)), "({}, {}) can't be reached", x, y);
+                }
+            }
+        }
+
+        // once joined up, nothing more is dug
+        assert!(!ensure_reachable(from, to, &mut map));
+    }
+
+    #[test]
+    fn every_cave_floor_tile_can_be_walked_to() {
+        for seed in 0..8 {
+            let mut objects = vec![crate::new_player()];
+            let map = make_cave_map(&mut objects, CAVE_MIN_LEVEL, Difficulty::Normal, &mut level_rng(seed, CAVE_MIN_LEVEL));
+            let (x, y) = objects[PLAYER].pos();
+            assert!(!map[x as usize][y as usize].blocked);
+            let floor = map.iter().flat_map(|column| column.iter()).filter(|tile| !tile.blocked).count();
+            assert_eq!(reachable_from(x, y, &map).len(), floor, "seed {} left a pocket cut off", seed);
+        }
+    }
+
+    #[test]
+    fn room_budgets_leave_some_rooms_empty_and_crowd_others() {
+        const LEVELS: u32 = 500;
+        const ROOMS: usize = 10;
+        let (mut rooms, mut empty, mut crowded) = (0, 0, 0);
+        for seed in 0..LEVELS {
+            let budgets = plan_room_budgets(ROOMS, 5, Difficulty::Normal, &mut level_rng(seed, 5));
+            assert_eq!(budgets.len(), ROOMS);
+            // the player's room is always safe, and the whole budget is handed out
+            assert_eq!(budgets[0].monsters, 0);
+            assert_eq!(budgets.iter().map(|budget| budget.monsters).sum::<u32>(), 15);
+            assert_eq!(budgets.iter().map(|budget| budget.items).sum::<u32>(), 10);
+            for budget in &budgets[1..] {
+                rooms += 1;
+                if budget.monsters == 0 {
+                    empty += 1;
+                } else if budget.monsters >= 5 {
+                    crowded += 1;
+                }
+            }
+        }
+        // safe rooms are always empty, and ordinary rooms can come up empty too
+        let empty = empty as f32 / rooms as f32;
+        let crowded = crowded as f32 / rooms as f32;
+        assert!(empty >= SAFE_ROOM_CHANCE as f32 / 100.0 && empty <= 0.55, "{} of rooms were empty", empty);
+        // an ordinary room rarely gets five monsters, a danger room often does
+        assert!((0.04..=0.2).contains(&crowded), "{} of rooms were crowded", crowded);
+    }
+}
@@ -0,0 +1,95 @@
+/// this file runs the game logic without a window, timing each part of a turn
+use crate::constants::*;
+use crate::user_defined::*;
+use crate::mapgen::*;
+use crate::helper::*;
+use crate::render::*;
+
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+/// time spent in each part of the game logic
+struct Timings {
+    generation: Duration,
+    fov: Duration,
+    lighting: Duration,
+    player: Duration,
+    world: Duration,
+}
+
+/// simulate a number of turns of a player wandering through freshly generated
+/// levels, then print how long each part of the game logic took
+pub fn run_profile(turns: u32, seed: u32) {
+    // the simulated player's choices come from a fixed seed, so runs can be compared
+    let mut rng = XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]);
+    let mut timings = Timings {
+        generation: Duration::new(0, 0),
+        fov: Duration::new(0, 0),
+        lighting: Duration::new(0, 0),
+        player: Duration::new(0, 0),
+        world: Duration::new(0, 0),
+    };
+
+    let mut objects = vec![crate::new_player()];
+    let mut game = Game::new(vec![], Settings::new(), seed, Difficulty::Normal, None);
+    let mut fov_map = crate::fov::new_fov(&game.settings);
+    let mut most_objects = 0;
+
+    for turn in 0..turns {
+        if turn % PROFILE_TURNS_PER_LEVEL == 0 {
+            let start = Instant::now();
+            game.map = make_level_map(&mut objects, game.dungeon_level, game.seed, game.difficulty);
+            fov_map = create_fov_map(&game);
+            timings.generation += start.elapsed();
+            game.dungeon_level += 1;
+        }
+
+        let start = Instant::now();
+        update_lighting(&objects, &mut game);
+        timings.lighting += start.elapsed();
+
+        let start = Instant::now();
+        let player = &objects[PLAYER];
+        fov_map.compute(player.x, player.y, player.sight_radius());
+        timings.fov += start.elapsed();
+
+        // the player wanders at random, attacking anything in the way
+        let start = Instant::now();
+        player_move_or_attack(rng.gen_range(-1, 2), rng.gen_range(-1, 2), false, &mut game, &mut objects);
+        timings.player += start.elapsed();
+
+        // the rest of the turn runs just like in the game
+        let start = Instant::now();
+        simulate_turn(&mut game, &mut objects, &fov_map);
+        drop_carried_items(&mut objects, &mut game);
+        remove_escaped(&mut objects);
+        timings.world += start.elapsed();
+        game.animations.clear(); // nothing to play them on
+        game.light_cache.flash.clear();
+
+        // keep the simulation going if the player dies
+        if !objects[PLAYER].alive || objects[PLAYER].fighter.map_or(true, |f| f.hp <= 0) {
+            let mut player = crate::new_player();
+            player.set_pos(objects[PLAYER].x, objects[PLAYER].y);
+            objects[PLAYER] = player;
+        }
+        most_objects = most_objects.max(objects.len());
+    }
+
+    let total = timings.generation + timings.fov + timings.lighting + timings.player + timings.world;
+    println!("Simulated {} turns (seed {}), at most {} objects on a level", turns, seed, most_objects);
+    for &(name, time) in &[
+        ("generation", timings.generation),
+        ("fov", timings.fov),
+        ("lighting", timings.lighting),
+        ("player", timings.player),
+        ("world", timings.world),
+        ("total", total),
+    ] {
+        println!("{:>12}: {:>10.3} ms, {:>8.3} ms/turn", name, millis(time), millis(time) / turns.max(1) as f64);
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
@@ -5,102 +5,115 @@ use crate::helper;
 
 use tcod::console::*;
 use tcod::colors::{self, Color};
-use tcod::map::{Map as FovMap}; // the 'Map as FovMap' section renames the tcod fov map
-                                // so that it doesn't conflict with our user defined Map
-use tcod::input::{self, Event, Mouse};
+use tcod::input::{self, Event, Key};
+use rand::Rng;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
 /// this function will handle all the rendering needed
 pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_recompute: bool) {
 
     let player = &objects[PLAYER];
     let player_lit = game.map[player.x as usize][player.y as usize].lit;
+    let palette = game.settings.palette.colors();
+    let dazzled = player.has_status(StatusKind::Dazzled);
+    move_camera(tcod, player.pos());
 
     if fov_recompute {
 
-        // find objects that emit light, and set their fovs as well
-        // we can't store them on the objects because we can't write the FOV to file when we save (and don't really want to)
-        let mut emitter_fovs = vec![];
-        for object in objects {
-            if object.emitter.is_some() {
-                // since it emits light, create an FOV
-                let mut fov_map = helper::create_fov_map(game);
-                fov_map.compute_fov(object.x, object.y, object.emitter.as_ref().map_or(0, |f| f.radius), FOV_LIGHT_WALLS, FOV_ALGO);
-                emitter_fovs.push(fov_map);
-            }
-        }
-
-        // we need to find out which tiles are lit so we can tell if the player is standing in the light
-        for y in 0..MAP_HEIGHT {
-            for x in 0..MAP_WIDTH {
-                // also visible if in the light of an emitter
-                let mut in_emitter_light: bool = false;
-                for fov in &emitter_fovs {
-                    in_emitter_light = fov.is_in_fov(x, y);
-                    if in_emitter_light == true {
-                        break;
-                    }
-                }
-                // if the tile is in the emmitter light, set it to lit, else set lit to false. This should let us
-                // light and unlight tiles, but allow previously lit tiles to be explored
-                let lit = &mut game.map[x as usize][y as usize].lit;
-                if in_emitter_light {
-                    *lit = true;
-                } else {
-                    *lit = false;
-                }
-            }
-        }
+        // find out which tiles are lit by emitters
+        update_lighting(objects, game);
 
         // recompute the player's FOV. if standing on a lit tile, use TORCH_RADIUS_IN_LIT_AREA
-        tcod.fov.compute_fov(player.x, player.y, player.fov_radius, FOV_LIGHT_WALLS, FOV_ALGO);
+        tcod.fov.compute(player.x, player.y, player.sight_radius());
+    }
+
+    // an explosion lights up the tiles around it for this one frame, without leaving them lit or explored
+    let flash: HashSet<(i32, i32)> = game.light_cache.flash.drain(..).collect();
+
+    // tiles that just went dark keep being redrawn, a little dimmer each frame, until they fade out
+    let fading = !game.light_cache.afterimages.is_empty();
+    if fov_recompute || fading || !flash.is_empty() {
+
+        // in the dark, the player can always feel the tiles right around them
+        let touch_sense = game.settings.touch_sense && !player_lit;
 
         // draw the map tiles, setting background colors
+        let mut discovered = 0;
         for y in 0..MAP_HEIGHT {
             for x in 0..MAP_WIDTH {
-                let visible_to_player = tcod.fov.is_in_fov(x, y); // this is the players fov
-                let wall = game.map[x as usize][y as usize].block_sight;
-                let lit_tile = game.map[x as usize][y as usize].lit;
-
-                // for now, make the tiles visible to the player or in emitter light the same color
-                // add a match thing for whether the player is lit, so we can move to greyscale
-                let mut color = match(visible_to_player || lit_tile, wall, player_lit) {
-                    // outside field of view
-                    (false, true, true) => COLOR_DARK_WALL,
-                    (false, true, false) => colors::DARKEST_GREY, //greyscale
-                    (false, false, true) => COLOR_DARK_GROUND,
-                    (false, false, false) => colors::DARKER_GREY, //greyscale
-                    // inside fov:COLOR_DARK_GROUND
-                    (true, true, true) => COLOR_LIGHT_WALL,
-                    (true, true, false) => colors::DARK_GREY, //greyscale
-                    (true, false, true) => COLOR_LIGHT_GROUND, 
-                    (true, false, false) => colors::GREY, //greyscale 
+                let felt = touch_sense && (x - player.x).abs() <= 1 && (y - player.y).abs() <= 1;
+                let visible_to_player = tcod.fov.is_in_fov(x, y) || felt; // this is the players fov
+                let tile = &game.map[x as usize][y as usize];
+                let flashed = flash.contains(&(x, y));
+                let lit_tile = tile.lit;
+                let light = if flashed {
+                    Some((FIREBALL_FLASH_LIGHT, colors::ORANGE))
+                } else if lit_tile {
+                    Some((tile.light, tile.light_color))
+                } else {
+                    None
                 };
+                let mut color = tile_color(tile, visible_to_player || lit_tile || flashed, light, player_lit, &palette);
 
-                // if lit by torch, adjust the color a smidge
-                if lit_tile {
-                    if player_lit {
-                        color = colors::lerp(color, colors::ORANGE, 0.5)
-                    } else {
-                        color = colors::lerp(color, colors::LIGHTER_GREY, 0.5)
+                // a tile whose light just went out fades from how it looked lit, rather than popping to dark.
+                // one never explored fades to black, since it was only seen in the flash
+                let afterimage = game.light_cache.afterimages.get(&(x, y));
+                if !lit_tile && !flashed {
+                    if let Some(afterimage) = afterimage {
+                        let lit_color = tile_color(tile, true, Some((afterimage.light, afterimage.color)), player_lit, &palette);
+                        let fade = afterimage.frames_left as f32 / (AFTERIMAGE_FRAMES + 1) as f32;
+                        let dark_color = if tile.explored { color } else { colors::BLACK };
+                        color = colors::lerp(dark_color, lit_color, fade);
                     }
                 }
+                let drawn = flashed || afterimage.is_some();
+
+                // a dazzled player sees the world washed out in white
+                if dazzled {
+                    color = colors::lerp(color, colors::WHITE, DAZZLE_WHITEN);
+                }
 
-                let explored = &mut game.map[x as usize][y as usize].explored;
-                if visible_to_player || lit_tile {
-                    // since it's visible, explore it
-                    *explored = true;
+                let tile = &mut game.map[x as usize][y as usize];
+                if (visible_to_player || lit_tile) && !tile.explored {
+                    // since it's visible, explore it. finding new tiles earns experience
+                    tile.explored = true;
+                    discovered += 1;
                 }
-                if *explored {
+                if tile.explored || drawn {
                     tcod.con.set_char_background(x, y, color, BackgroundFlag::Set);
                 }
             }
         }
+        game.explore_progress += discovered;
+
+        for afterimage in game.light_cache.afterimages.values_mut() {
+            afterimage.frames_left -= 1;
+        }
+        let (map, con) = (&game.map, &mut tcod.con);
+        game.light_cache.afterimages.retain(|&(x, y), afterimage| {
+            // a tile only seen in a flash goes back to unexplored black once it's faded
+            if afterimage.frames_left <= 0 && !map[x as usize][y as usize].explored {
+                con.set_char_background(x, y, colors::BLACK, BackgroundFlag::Set);
+            }
+            afterimage.frames_left > 0
+        });
+
+        // the flash is over after this frame, and fades out like any other light
+        for &pos in &flash {
+            if !game.map[pos.0 as usize][pos.1 as usize].lit {
+                let afterimage = Afterimage{light: FIREBALL_FLASH_LIGHT, color: colors::ORANGE, frames_left: AFTERIMAGE_FRAMES};
+                game.light_cache.afterimages.insert(pos, afterimage);
+            }
+        }
     }
 
     // draw objects that are a) in players fov b) in a lit area c) are always visible and in an explored area
-    let mut to_draw: Vec<_> = objects.iter().filter(|o| {
+    let mut to_draw: Vec<_> = objects.iter().filter(|o| !o.is_hidden()).filter(|o| {
         tcod.fov.is_in_fov(o.x, o.y) || 
         game.map[o.x as usize][o.y as usize].lit ||
+        flash.contains(&o.pos()) ||
         (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
     }).collect();
 
@@ -114,7 +127,7 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
         }
     } else {
         for object in &to_draw {
-            object.draw_black(&mut tcod.con);
+            object.draw_dark(&mut tcod.con, palette.dark_object);
         }
     }
 
@@ -127,21 +140,60 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
     let max_hp = objects[PLAYER].max_hp(game);
     // if player is standing in a lit tile, use red, else grey
     if player_lit {
-        render_bar(&mut tcod.panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, colors::LIGHT_RED, colors::DARKER_RED);
+        render_bar(&mut tcod.panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, palette.hp_bar, palette.hp_back);
     } else {
         render_bar(&mut tcod.panel, 1, 1, BAR_WIDTH, "HP", hp, max_hp, colors::DARKER_GREY, colors::DARKEST_GREY);
     }
 
-    // show the level of the dungeon
-    tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left,
-        format!("Dungeon level: {}", game.dungeon_level));
+    // how far the player's eyes have adjusted: + while they widen in the dark, - while they narrow in the light
+    let player = &objects[PLAYER];
+    let target = if player_lit { TORCH_RADIUS_IN_LIT_AREA } else { TORCH_RADIUS_IN_DARK_AREA } as f32;
+    let eyes = if player.fov_radius < target {
+        "Eyes+"
+    } else if player.fov_radius > target {
+        "Eyes-"
+    } else {
+        "Eyes"
+    };
+    if player_lit {
+        render_bar(&mut tcod.panel, 1, 2, BAR_WIDTH, eyes, player.sight_radius(), TORCH_RADIUS_IN_DARK_AREA,
+            colors::LIGHT_AMBER, colors::DARKER_AMBER);
+    } else {
+        render_bar(&mut tcod.panel, 1, 2, BAR_WIDTH, eyes, player.sight_radius(), TORCH_RADIUS_IN_DARK_AREA,
+            colors::GREY, colors::DARKEST_GREY);
+    }
 
-    // show whether the player is in a lit or dark tile
-    tcod.panel.print_ex(1, 5, BackgroundFlag::None, TextAlignment::Left,
-        match player_lit {
-            true => "Lit",
-            false => "Dark",
-        });
+    // show the level of the dungeon (level 0 is the prologue), and how dark it is
+    let dungeon_level = match game.dungeon_level {
+        0 => "Prologue".to_string(),
+        level => format!("Dungeon level: {} ({})", level, crate::mapgen::darkness(level)),
+    };
+    tcod.panel.print_ex(1, 3, BackgroundFlag::None, TextAlignment::Left, dungeon_level);
+
+    // how many turns the run has taken, and arrows left in the quiver once there are any
+    let turns = if game.arrows > 0 {
+        format!("Turn {}  Arrows: {}", game.turn_count, game.arrows)
+    } else {
+        format!("Turn {}", game.turn_count)
+    };
+    tcod.panel.print_ex(1, 4, BackgroundFlag::None, TextAlignment::Left, turns);
+
+    // show whether the player is in a lit or dark tile (hidden, if nothing awake can see them there), and how hungry they are
+    let light = match (player_lit, crate::ai::player_hidden(game, objects)) {
+        (true, _) => "Lit",
+        (false, true) => "Hidden",
+        (false, false) => "Dark",
+    };
+    let status = match helper::hunger_status(game) {
+        Some(hunger) => format!("{}  {}", light, hunger),
+        None => light.to_string(),
+    };
+    tcod.panel.print_ex(1, 5, BackgroundFlag::None, TextAlignment::Left, status);
+
+    // list any status effects the player is under
+    let statuses: Vec<String> = objects[PLAYER].status_effects.iter()
+        .map(|effect| format!("{} ({})", effect.kind, effect.turns_left)).collect();
+    tcod.panel.print_ex(1, 6, BackgroundFlag::None, TextAlignment::Left, statuses.join(", "));
 
     // print the game messages, one line at a time
     let mut y = MSG_HEIGHT as i32;
@@ -161,16 +213,249 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
     }
 
     // display names of objects under the mouse
+    let mouse_pos = to_map_coordinates(tcod, tcod.mouse.cx as i32, tcod.mouse.cy as i32);
     tcod.panel.set_default_foreground(colors::LIGHT_GREY);
     tcod.panel.print_ex(1, 0, BackgroundFlag::None, TextAlignment::Left, 
-                   get_names_under_mouse(tcod.mouse, objects, &mut tcod.fov));
+                   get_names_under_mouse(mouse_pos, objects, game, &mut tcod.fov));
 
     // blit the contents of the 'panel' to the root console
     blit(&tcod.panel, (0, 0), (SCREEN_WIDTH, PANEL_HEIGHT), &mut tcod.root, (0, PANEL_Y), 1.0, 1.0);
 
-    // blit the con to the root
-    blit(&tcod.con, (0, 0), (MAP_WIDTH, MAP_HEIGHT), &mut tcod.root, (0, 0), 1.0, 1.0); 
+    // blit the part of the con the camera is looking at to the root
+    let view = (cmp::min(MAP_WIDTH, CAMERA_WIDTH), cmp::min(MAP_HEIGHT, CAMERA_HEIGHT));
+    blit(&tcod.con, tcod.camera, view, &mut tcod.root, (0, 0), 1.0, 1.0); 
+
+    // monster health bars go straight onto the root, so they don't linger on the map after a monster moves
+    for object in &to_draw {
+        if object.faction != Faction::Player {
+            if let (Some(fighter), Some((x, y))) = (object.fighter, to_camera_coordinates(tcod, object.x, object.y)) {
+                render_monster_hp(&mut tcod.root, x, y, fighter.hp, object.max_hp(game), player_lit, &palette);
+            }
+        }
+    }
+}
+
+/// play the animations queued up during the turn, one frame per flush (so at the
+/// frame rate limit). only what the player can see is shown, and any key skips the rest
+pub fn play_animations(tcod: &mut Tcod, objects: &[Object], game: &mut Game) {
+    let animations: Vec<Animation> = game.animations.drain(..).collect();
+    let player_lit = game.map[objects[PLAYER].x as usize][objects[PLAYER].y as usize].lit;
+    for animation in animations {
+        // each frame is a tile to draw, with the character to put there (if any) and a color
+        let frames: Vec<((i32, i32), Option<char>, Color)> = match animation {
+            Animation::Flash{pos} => {
+                let color = if player_lit { game.settings.palette.colors().hp_bar } else { colors::LIGHTEST_GREY };
+                (0..FLASH_FRAMES).map(|_| (pos, None, color)).collect()
+            },
+            Animation::Projectile{from, to, char, color} => {
+                let color = if player_lit { color } else { colors::LIGHTEST_GREY };
+                helper::line_between(from, to).into_iter().map(|pos| (pos, Some(char), color)).collect()
+            },
+        };
+        for (pos, char, color) in frames {
+            let visible = tcod.fov.is_in_fov(pos.0, pos.1) || game.map[pos.0 as usize][pos.1 as usize].lit;
+            let screen_pos = match to_camera_coordinates(tcod, pos.0, pos.1) {
+                Some(screen_pos) if visible => screen_pos,
+                _ => continue,
+            };
+            render_all(tcod, objects, game, false);
+            match char {
+                Some(char) => {
+                    tcod.root.set_default_foreground(color);
+                    tcod.root.put_char(screen_pos.0, screen_pos.1, char, BackgroundFlag::None);
+                },
+                None => tcod.root.set_char_background(screen_pos.0, screen_pos.1, color, BackgroundFlag::Set),
+            }
+            tcod.root.flush();
+            if input::check_for_event(input::KEY_PRESS).is_some() {
+                return;
+            }
+        }
+    }
+}
+
+/// center the camera on a map position, stopping at the edges of the map
+pub fn move_camera(tcod: &mut Tcod, (x, y): (i32, i32)) {
+    let camera_x = cmp::max(0, cmp::min(MAP_WIDTH - CAMERA_WIDTH, x - CAMERA_WIDTH / 2));
+    let camera_y = cmp::max(0, cmp::min(MAP_HEIGHT - CAMERA_HEIGHT, y - CAMERA_HEIGHT / 2));
+    tcod.camera = (camera_x, camera_y);
+}
+
+/// where a map position is on the screen, or None if the camera can't see it
+pub fn to_camera_coordinates(tcod: &Tcod, x: i32, y: i32) -> Option<(i32, i32)> {
+    let (x, y) = (x - tcod.camera.0, y - tcod.camera.1);
+    if x < 0 || y < 0 || x >= CAMERA_WIDTH || y >= CAMERA_HEIGHT {
+        None
+    } else {
+        Some((x, y))
+    }
+}
+
+/// the map position under a point on the screen
+pub fn to_map_coordinates(tcod: &Tcod, x: i32, y: i32) -> (i32, i32) {
+    (x + tcod.camera.0, y + tcod.camera.1)
+}
 
+/// draw a tiny hp bar in the row above a monster, greyed out in the dark
+fn render_monster_hp(root: &mut Root, x: i32, y: i32, hp: i32, max_hp: i32, player_lit: bool, palette: &Palette) {
+    if y == 0 || max_hp <= 0 {
+        return;
+    }
+    let (bar_color, back_color) = if player_lit {
+        (palette.hp_bar, palette.hp_back)
+    } else {
+        (colors::DARKER_GREY, colors::DARKEST_GREY)
+    };
+    // round up, so a monster with any hp left shows at least one filled cell
+    let filled = (MONSTER_HP_BAR_WIDTH * hp + max_hp - 1) / max_hp;
+    let start_x = x - MONSTER_HP_BAR_WIDTH / 2;
+    for i in 0..MONSTER_HP_BAR_WIDTH {
+        let bar_x = start_x + i;
+        if bar_x >= 0 && bar_x < CAMERA_WIDTH {
+            let color = if i < filled { bar_color } else { back_color };
+            root.set_char_background(bar_x, y - 1, color, BackgroundFlag::Set);
+        }
+    }
+}
+
+/// the background color of a map tile: in view or remembered, in color or greyscale
+/// depending on whether the player is lit, tinted by its terrain and by any light on it
+fn tile_color(tile: &Tile, in_view: bool, light: Option<(f32, Color)>, player_lit: bool, palette: &Palette) -> Color {
+    // for now, make the tiles visible to the player or in emitter light the same color
+    // add a match thing for whether the player is lit, so we can move to greyscale
+    let mut color = match(in_view, tile.block_sight, player_lit) {
+        // outside field of view
+        (false, true, true) => palette.dark_wall,
+        (false, true, false) => palette.grey_dark_wall, //greyscale
+        (false, false, true) => palette.dark_ground,
+        (false, false, false) => palette.grey_dark_ground, //greyscale
+        // inside fov
+        (true, true, true) => palette.light_wall,
+        (true, true, false) => palette.grey_light_wall, //greyscale
+        (true, false, true) => palette.light_ground,
+        (true, false, false) => palette.grey_light_ground, //greyscale
+    };
+
+    // water and lava show through, faintly in the dark
+    let terrain_color = match tile.terrain {
+        Terrain::Ground => None,
+        Terrain::Water => Some(palette.water),
+        Terrain::Lava => Some(palette.lava),
+    };
+    if let Some(terrain_color) = terrain_color {
+        let tint = if player_lit { TERRAIN_TINT_COLOR } else { TERRAIN_TINT_GREY };
+        color = colors::lerp(color, terrain_color, tint);
+    }
+
+    // if lit by torch, adjust the color more the closer it is to the torch
+    if let Some((light, light_color)) = light {
+        let blend = light * MAX_LIGHT_BLEND;
+        if player_lit {
+            color = colors::lerp(color, palette.torchlight.unwrap_or(light_color), blend)
+        } else {
+            color = colors::lerp(color, colors::LIGHTER_GREY, blend)
+        }
+    }
+    color
+}
+
+/// mark every tile in the light of an emitter as lit, and every other tile as unlit
+pub fn update_lighting(objects: &[Object], game: &mut Game) {
+    // the cached emitter fovs only hold as long as the walls stay where they were
+    let block_sight: Vec<bool> = game.map.iter()
+        .flat_map(|column| column.iter().map(|tile| tile.block_sight))
+        .collect();
+    let mut cache = mem::replace(&mut game.light_cache, LightCache::default());
+    if cache.level != game.dungeon_level || cache.block_sight != block_sight {
+        // a different map, so any tile might hold stale light
+        let every_tile = (0..MAP_WIDTH).flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y))).collect();
+        cache = LightCache {level: game.dungeon_level, block_sight: block_sight, fovs: HashMap::new(), lit_tiles: every_tile, afterimages: HashMap::new(), flash: vec![]};
+    }
+    if cache.fovs.len() > LIGHT_CACHE_SIZE {
+        cache.fovs.clear();
+    }
+
+    // find objects that emit light, and the tiles each one reaches
+    // the fov map is only built if some emitter isn't cached yet
+    let mut fov_map: Option<Box<dyn FieldOfView>> = None;
+    let mut emitters = vec![];
+    for object in objects {
+        if let Some(emitter) = object.emitter.as_ref() {
+            // a torch that's nearly out flickers, growing or shrinking a little each frame
+            let radius = if emitter.fuel < TORCH_FLICKER_FUEL {
+                emitter.radius + rand::thread_rng().gen_range(-1, 2)
+            } else {
+                emitter.radius
+            };
+            let key = (object.x, object.y, radius);
+            if !cache.fovs.contains_key(&key) {
+                let fov_map = fov_map.get_or_insert_with(|| helper::create_fov_map(game));
+                fov_map.compute(object.x, object.y, radius);
+                // no light reaches past the radius, so only its bounding box needs checking
+                let reach = radius.max(0);
+                let mut tiles = vec![];
+                for x in cmp::max(0, object.x - reach)..cmp::min(MAP_WIDTH, object.x + reach + 1) {
+                    for y in cmp::max(0, object.y - reach)..cmp::min(MAP_HEIGHT, object.y + reach + 1) {
+                        if fov_map.is_in_fov(x, y) {
+                            tiles.push((x, y));
+                        }
+                    }
+                }
+                cache.fovs.insert(key, tiles);
+            }
+            emitters.push((key, emitter.color));
+        }
+    }
+
+    // the light fades with distance, so take the strongest light reaching each tile
+    // overlapping emitters mix their colors, weighted by how strongly each one reaches the tile
+    let mut lights: HashMap<(i32, i32), (f32, f32, f32, f32, f32)> = HashMap::new();
+    for &((emitter_x, emitter_y, radius), color) in &emitters {
+        for &(x, y) in &cache.fovs[&(emitter_x, emitter_y, radius)] {
+            let intensity = light_intensity(x - emitter_x, y - emitter_y, radius);
+            let &mut (ref mut light, ref mut total_intensity, ref mut r, ref mut g, ref mut b) =
+                lights.entry((x, y)).or_insert((0.0, 0.0, 0.0, 0.0, 0.0));
+            *light = light.max(intensity);
+            *total_intensity += intensity;
+            *r += color.r as f32 * intensity;
+            *g += color.g as f32 * intensity;
+            *b += color.b as f32 * intensity;
+        }
+    }
+
+    // darken whatever was lit before, then light the tiles reached now. a tile bright enough is lit,
+    // which lets us light and unlight tiles, but allow previously lit tiles to be explored
+    for &(x, y) in &cache.lit_tiles {
+        let tile = &mut game.map[x as usize][y as usize];
+        // a tile that stops being lit leaves an afterimage behind, see render_all
+        if tile.lit && !lights.get(&(x, y)).map_or(false, |&(light, ..)| light >= LIT_THRESHOLD) {
+            cache.afterimages.insert((x, y), Afterimage{light: tile.light, color: tile.light_color, frames_left: AFTERIMAGE_FRAMES});
+        }
+        tile.light = 0.0;
+        tile.lit = false;
+        tile.light_color = colors::BLACK;
+    }
+    for (&(x, y), &(light, total_intensity, r, g, b)) in &lights {
+        let tile = &mut game.map[x as usize][y as usize];
+        tile.light = light;
+        tile.lit = light >= LIT_THRESHOLD;
+        tile.light_color = if total_intensity > 0.0 {
+            Color::new((r / total_intensity).round() as u8,
+                       (g / total_intensity).round() as u8,
+                       (b / total_intensity).round() as u8)
+        } else {
+            colors::BLACK
+        };
+    }
+    cache.lit_tiles = lights.keys().cloned().collect();
+    cache.afterimages.retain(|pos, _| !game.map[pos.0 as usize][pos.1 as usize].lit);
+    game.light_cache = cache;
+}
+
+/// how brightly an emitter lights a tile at the given offset: full at the emitter, fading towards its radius
+fn light_intensity(dx: i32, dy: i32, radius: i32) -> f32 {
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+    (1.0 - distance / (radius + 1) as f32).max(0.0)
 }
 
 fn render_bar(panel: &mut Offscreen,
@@ -201,20 +486,23 @@ fn render_bar(panel: &mut Offscreen,
                    &format!("{}: {}/{}", name, value, maximum));
 }
 
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
-
+fn get_names_under_mouse((x, y): (i32, i32), objects: &[Object], game: &Game, fov_map: &dyn FieldOfView) -> String {
     // create a list with the names of all objects at the mouse's coordinates and in fov
     let names = objects
         .iter()
         .filter(|obj| {obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y)})
-        .map(|obj| obj.name.clone())
+        .map(|obj| helper::item_name(obj, game))
         .collect::<Vec<_>>();
 
     names.join(", ") // join the names, separated by commas
 }
 
 pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
+    menu_key(header, options, width, root).0
+}
+
+/// like menu, but also hands back the key that was pressed, for menus with keys of their own
+pub fn menu_key<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> (Option<usize>, Key) {
     // cannot have more than 26 options (a-z)
     assert!(options.len() <= 26, "Cannot have a menu with more than 26 options.");
 
@@ -250,7 +538,7 @@ pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut R
     let key = root.wait_for_keypress(true);
 
     // convert the ASCII code to an index; if it correspons to an option, return it
-    if key.printable.is_alphabetic() {
+    let choice = if key.printable.is_alphabetic() {
         let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
         if index < options.len() {
             Some(index)
@@ -259,7 +547,175 @@ pub fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut R
         }
     } else {
         None
+    };
+    (choice, key)
+}
+
+/// show the whole message history full-screen, newest at the bottom, scrolled with
+/// the arrow and page keys until Escape is pressed
+pub fn message_log_screen(game: &Game, player_lit: bool, root: &mut Root) {
+    // starts with the newest messages
+    scroll_screen("Message log", &game.log, game.log.len(), player_lit, root);
+}
+
+/// every monster and item in the catalog, with what's known about the ones met this run.
+/// anything not met yet, or a scroll not identified yet, is just "???"
+pub fn bestiary_screen(game: &Game, player_lit: bool, root: &mut Root) {
+    let unknown = ("???".to_string(), colors::DARK_GREY);
+    let mut lines = vec![("Monsters".to_string(), colors::LIGHT_RED)];
+    for &(name, description) in MONSTER_CATALOG {
+        if game.seen_monsters.iter().any(|seen| seen == name) {
+            lines.push((format!("{}: {}", name, description), colors::WHITE));
+        } else {
+            lines.push(unknown.clone());
+        }
     }
+    lines.push((" ".to_string(), colors::WHITE));
+    lines.push(("Items".to_string(), colors::LIGHT_YELLOW));
+    for &(item, name, description) in ITEM_CATALOG {
+        let known = game.seen_items.contains(&item) && (!item.is_scroll() || game.identified.contains(&item));
+        if known {
+            lines.push((format!("{}: {}", name, description), colors::WHITE));
+        } else {
+            lines.push(unknown.clone());
+        }
+    }
+
+    // start scrolled to the top: as many lines as fit below the title
+    let mut height = 1;
+    let last = lines.iter().take_while(|&&(ref line, _)| {
+        height += root.get_height_rect(0, 0, SCREEN_WIDTH, 0, line);
+        height <= SCREEN_HEIGHT
+    }).count();
+    scroll_screen("Bestiary", &lines, last, player_lit, root);
+}
+
+/// a shrunken view of the whole level, drawn over the map until a key is pressed.
+/// only what's been explored shows: floor, walls, the player, and things that stay visible once found
+pub fn minimap_screen(objects: &[Object], game: &Game, root: &mut Root) {
+    let width = (MAP_WIDTH + MINIMAP_SCALE - 1) / MINIMAP_SCALE;
+    let height = (MAP_HEIGHT + MINIMAP_SCALE - 1) / MINIMAP_SCALE;
+    let player = &objects[PLAYER];
+    let player_lit = game.map[player.x as usize][player.y as usize].lit;
+    let palette = game.settings.palette.colors();
+    let (wall_color, ground_color) = if player_lit {
+        (palette.light_wall, palette.light_ground)
+    } else {
+        (palette.grey_light_wall, palette.grey_light_ground)
+    };
+
+    // the title goes on the top line, above the map
+    let mut window = Offscreen::new(width, height + 1);
+    window.set_default_background(colors::BLACK);
+    window.clear();
+    window.set_default_foreground(colors::LIGHT_GREY);
+    window.print_ex(0, 0, BackgroundFlag::None, TextAlignment::Left, "Map: any key to close");
+
+    // each cell shows floor if any of its tiles is explored floor, otherwise wall if any is explored wall
+    for cell_x in 0..width {
+        for cell_y in 0..height {
+            let mut color = None;
+            for x in (cell_x * MINIMAP_SCALE)..cmp::min(MAP_WIDTH, (cell_x + 1) * MINIMAP_SCALE) {
+                for y in (cell_y * MINIMAP_SCALE)..cmp::min(MAP_HEIGHT, (cell_y + 1) * MINIMAP_SCALE) {
+                    let tile = &game.map[x as usize][y as usize];
+                    if !tile.explored {
+                        continue;
+                    }
+                    if !tile.block_sight {
+                        color = Some(ground_color);
+                    } else if color.is_none() {
+                        color = Some(wall_color);
+                    }
+                }
+            }
+            if let Some(color) = color {
+                window.set_char_background(cell_x, cell_y + 1, color, BackgroundFlag::Set);
+            }
+        }
+    }
+
+    // stairs, items and the like, then the player on top
+    let found = objects.iter().filter(|object| {
+        object.always_visible && !object.is_hidden() && game.map[object.x as usize][object.y as usize].explored
+    });
+    for object in found.chain(Some(player)) {
+        let color = if player_lit { object.color } else { colors::WHITE };
+        window.set_default_foreground(color);
+        window.put_char(object.x / MINIMAP_SCALE, object.y / MINIMAP_SCALE + 1, object.char, BackgroundFlag::None);
+    }
+
+    // over the middle of the map, leaving the panel in view
+    let x = (SCREEN_WIDTH - width) / 2;
+    let y = cmp::max(0, (CAMERA_HEIGHT - height - 1) / 2);
+    blit(&window, (0, 0), (width, height + 1), root, (x, y), 1.0, 1.0);
+    root.flush();
+    root.wait_for_keypress(true);
+}
+
+/// a full screen list of colored lines, scrolled with the arrows and Page Up/Page Down.
+/// lines are shown up to (not including) `last`, filling the screen from the bottom up
+fn scroll_screen(title: &str, lines: &[(String, Color)], last: usize, player_lit: bool, root: &mut Root) {
+    use tcod::input::KeyCode::*;
+    let mut window = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut last = last;
+    let first_page = cmp::min(1, lines.len());
+    loop {
+        window.set_default_background(colors::BLACK);
+        window.clear();
+        window.set_default_foreground(colors::LIGHT_GREY);
+        window.print_ex(0, 0, BackgroundFlag::None, TextAlignment::Left,
+            format!("{}: arrows/Page Up/Page Down to scroll, Escape to close", title));
+
+        // fill the screen from the bottom up
+        let mut y = SCREEN_HEIGHT;
+        for &(ref msg, color) in lines[..last].iter().rev() {
+            y -= window.get_height_rect(0, 0, SCREEN_WIDTH, 0, msg);
+            if y < 1 {
+                break;
+            }
+            // if player is standing in a lit tile, use color, else just white
+            if player_lit {
+                window.set_default_foreground(color);
+            } else {
+                window.set_default_foreground(colors::WHITE);
+            }
+            window.print_rect(0, y, SCREEN_WIDTH, 0, msg);
+        }
+
+        blit(&window, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+        root.flush();
+        let key = root.wait_for_keypress(true);
+        let page = (SCREEN_HEIGHT - 1) as usize;
+        last = match key.code {
+            Up | NumPad8 => cmp::max(first_page, last.saturating_sub(1)),
+            Down | NumPad2 => cmp::min(lines.len(), last + 1),
+            PageUp | NumPad9 => cmp::max(first_page, last.saturating_sub(page)),
+            PageDown | NumPad3 => cmp::min(lines.len(), last + page),
+            Escape => break,
+            _ => last,
+        };
+    }
+}
+
+/// a full screen summary of a run that's over, shown until a key is pressed.
+/// in the dark the title loses its color, like the message log
+pub fn run_summary_screen(title: &str, title_color: Color, body: &str, player_lit: bool, root: &mut Root) {
+    let mut window = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    window.set_default_background(colors::BLACK);
+    window.clear();
+
+    let body_height = window.get_height_rect(0, 0, SCREEN_WIDTH, SCREEN_HEIGHT, body);
+    let y = (SCREEN_HEIGHT - body_height) / 2 - 2;
+    window.set_default_foreground(if player_lit { title_color } else { colors::WHITE });
+    window.print_ex(SCREEN_WIDTH / 2, y, BackgroundFlag::None, TextAlignment::Center, title);
+    window.set_default_foreground(colors::LIGHT_GREY);
+    window.print_rect_ex(SCREEN_WIDTH / 2, y + 2, SCREEN_WIDTH, body_height, BackgroundFlag::None, TextAlignment::Center, body);
+    window.print_ex(SCREEN_WIDTH / 2, SCREEN_HEIGHT - 2, BackgroundFlag::None, TextAlignment::Center,
+        "Press any key to return to the main menu");
+
+    blit(&window, (0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), root, (0, 0), 1.0, 1.0);
+    root.flush();
+    root.wait_for_keypress(true);
 }
 
 pub fn msgbox(text: &str, width: i32, root: &mut Root) {
@@ -267,13 +723,47 @@ pub fn msgbox(text: &str, width: i32, root: &mut Root) {
     menu(text, options, width, root);
 }
 
-/// return the position of a tile left-clicked in player's FOV (optionally in a 
-/// range), or (None, None) if right clicked.
+/// ask the player to type in a number, finished with Enter. returns None if they
+/// press Escape, or Enter without typing anything
+pub fn number_prompt(header: &str, width: i32, root: &mut Root) -> Option<u32> {
+    use tcod::input::KeyCode::*;
+    let mut digits = String::new();
+    loop {
+        // redraw the box with whatever has been typed so far
+        let text = format!("{}\n> {}_", header, digits);
+        let height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, &text);
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(colors::WHITE);
+        window.print_rect_ex(0, 0, width, height, BackgroundFlag::None, TextAlignment::Left, &text);
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        tcod::console::blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Escape => return None,
+            Enter | NumPadEnter => return digits.parse().ok(),
+            Backspace => { digits.pop(); },
+            // stop short of overflowing a u32
+            _ if key.printable.is_ascii_digit() && digits.len() < 9 => digits.push(key.printable),
+            _ => {},
+        }
+    }
+}
+
+/// return the position of a tile in player's FOV (optionally in a range) picked
+/// with a left-click, or by moving a cursor with the movement keys and pressing
+/// Enter. returns None if the player right-clicked or pressed Escape.
+/// while aiming, the edge of the range and any blast area around the cursor are tinted
 pub fn target_tile(tcod: &mut Tcod,
                 objects: &[Object],
                 game: &mut Game,
-                max_range: Option<f32>) -> Option<(i32, i32)> {
-    use tcod::input::KeyCode::Escape;
+                max_range: Option<f32>,
+                blast_radius: Option<i32>) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::*;
+    // the keyboard cursor starts on the player, and follows the mouse when it moves
+    let mut cursor = objects[PLAYER].pos();
     loop {
         // render the screen. This erases the inventory and shows the names of
         // objects under the mouse.
@@ -281,18 +771,40 @@ pub fn target_tile(tcod: &mut Tcod,
         let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
         let mut key = None;
         match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
+            Some(Event::Mouse(m)) => {
+                tcod.mouse = m;
+                cursor = to_map_coordinates(tcod, m.cx as i32, m.cy as i32);
+            },
             Some(Event::Key(k)) => key = Some(k),
             None => {}
         }
         render_all(tcod, objects, game, false);
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
 
-        // accept the target if the player clicked in FOV, and in case a range
+        // move the cursor with the same keys used for walking
+        if let Some(key) = key {
+            cursor = move_cursor(cursor, key);
+        }
+        let (x, y) = cursor;
+
+        // accept the target if the player picked a tile in FOV, and in case a range
         // is specified, if  it's within that range
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
+        let on_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+        let in_fov = on_map && tcod.fov.is_in_fov(x, y);
+        let in_range = max_range.map_or(true, |range| {
+            helper::game_distance(game, objects[PLAYER].pos(), (x, y)) <= range
+        });
+
+        // the overlay is drawn straight onto the root too, so it's gone once targeting ends
+        draw_target_overlay(tcod, objects, game, cursor, max_range, blast_radius);
+
+        // highlight the cursor, drawn straight onto the root so the next render wipes it
+        if let Some((screen_x, screen_y)) = to_camera_coordinates(tcod, x, y) {
+            let cursor_color = if in_fov && in_range { colors::LIGHT_GREEN } else { colors::LIGHT_RED };
+            tcod.root.set_char_background(screen_x, screen_y, cursor_color, BackgroundFlag::Set);
+        }
+
+        let confirm = key.map_or(false, |k| k.code == Enter || k.code == NumPadEnter);
+        if (tcod.mouse.lbutton_pressed || confirm) && in_fov && in_range {
             return Some((x, y))
         }
 
@@ -303,16 +815,126 @@ pub fn target_tile(tcod: &mut Tcod,
     }
 }
 
+/// move a map cursor with the same keys used for walking, keeping it on the map
+fn move_cursor(cursor: (i32, i32), key: Key) -> (i32, i32) {
+    use tcod::input::KeyCode::*;
+    let (dx, dy) = match key.code {
+        Up | NumPad8 => (0, -1),
+        Down | NumPad2 => (0, 1),
+        Left | NumPad4 => (-1, 0),
+        Right | NumPad6 => (1, 0),
+        Home | NumPad7 => (-1, -1),
+        PageUp | NumPad9 => (1, -1),
+        End | NumPad1 => (-1, 1),
+        PageDown | NumPad3 => (1, 1),
+        _ => (0, 0),
+    };
+    (cmp::max(0, cmp::min(MAP_WIDTH - 1, cursor.0 + dx)),
+     cmp::max(0, cmp::min(MAP_HEIGHT - 1, cursor.1 + dy)))
+}
+
+/// look around the map with a keyboard cursor, describing whatever is under it
+/// in the panel, until Escape is pressed
+pub fn look_mode(tcod: &mut Tcod, objects: &[Object], game: &mut Game) {
+    use tcod::input::KeyCode::Escape;
+    let mut cursor = objects[PLAYER].pos();
+    loop {
+        render_all(tcod, objects, game, false);
+        if let Some((x, y)) = to_camera_coordinates(tcod, cursor.0, cursor.1) {
+            tcod.root.set_char_background(x, y, colors::LIGHT_GREEN, BackgroundFlag::Set);
+        }
+
+        // the description replaces the top line of the panel while looking
+        tcod.root.set_default_background(colors::BLACK);
+        tcod.root.rect(0, PANEL_Y, SCREEN_WIDTH, 1, true, BackgroundFlag::Set);
+        tcod.root.set_default_foreground(colors::LIGHT_GREY);
+        tcod.root.print_ex(1, PANEL_Y, BackgroundFlag::None, TextAlignment::Left,
+            describe_tile(cursor, tcod, objects, game));
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        if key.code == Escape {
+            break;
+        }
+        cursor = move_cursor(cursor, key);
+    }
+}
+
+/// a short description of what the player can see on a tile
+fn describe_tile(pos: (i32, i32), tcod: &Tcod, objects: &[Object], game: &Game) -> String {
+    let (x, y) = pos;
+    let tile = &game.map[x as usize][y as usize];
+    let visible = tcod.fov.is_in_fov(x, y) || tile.lit;
+    if !visible && !tile.explored {
+        return "You haven't been there.".into();
+    }
+
+    let descriptions: Vec<String> = objects.iter()
+        .filter(|object| object.pos() == pos && (visible || object.always_visible) && !object.is_hidden())
+        .map(|object| match object.fighter {
+            Some(fighter) if object.faction != Faction::Player => {
+                let state = match object.ai {
+                    Some(Ai::Sleeping{..}) => ", asleep",
+                    Some(Ai::Confused{..}) => ", confused",
+                    Some(Ai::Frozen{..}) => ", frozen",
+                    Some(Ai::Fleeing) => ", fleeing",
+                    _ => "",
+                };
+                format!("{} ({}/{} hp{})", object.name, fighter.hp, object.max_hp(game), state)
+            },
+            Some(_) => "you".into(),
+            None => helper::item_name(object, game),
+        })
+        .collect();
+
+    if !descriptions.is_empty() {
+        descriptions.join(", ")
+    } else if tile.blocked {
+        "a wall".into()
+    } else {
+        "the floor".into()
+    }
+}
+
+/// tint the tiles at the edge of a spell's range, and the tiles its blast would catch
+fn draw_target_overlay(tcod: &mut Tcod, objects: &[Object], game: &Game, cursor: (i32, i32),
+                       max_range: Option<f32>, blast_radius: Option<i32>) {
+    let player_pos = objects[PLAYER].pos();
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let in_blast = blast_radius.map_or(false, |radius| {
+                helper::game_distance(game, cursor, (x, y)) <= radius as f32
+            });
+            let on_range_edge = max_range.map_or(false, |range| {
+                let distance = helper::game_distance(game, player_pos, (x, y));
+                distance <= range && distance > range - 1.0
+            });
+            let tint = if in_blast {
+                colors::ORANGE
+            } else if on_range_edge {
+                colors::LIGHT_BLUE
+            } else {
+                continue;
+            };
+            if let Some((x, y)) = to_camera_coordinates(tcod, x, y) {
+                let background = tcod.root.get_char_background(x, y);
+                tcod.root.set_char_background(x, y, colors::lerp(background, tint, TARGET_OVERLAY_BLEND), BackgroundFlag::Set);
+            }
+        }
+    }
+}
+
 pub fn target_monster(tcod: &mut Tcod,
                 objects: &[Object],
                 game: &mut Game,
                 max_range: Option<f32>) -> Option<usize> {
+    let index = ObjectIndex::new(objects);
     loop {
-        match target_tile(tcod, objects, game, max_range) {
+        match target_tile(tcod, objects, game, max_range, None) {
             Some((x, y)) => {
-                // return the first clicked monster, otherwise continue looping
-                for (id, obj) in objects.iter().enumerate() {
-                    if obj.pos() == (x, y) && obj.fighter.is_some()  && id != PLAYER {
+                // return the first targeted monster, otherwise continue looping
+                for &id in index.at((x, y)) {
+                    if objects[id].fighter.is_some() && id != PLAYER {
                         return Some(id)
                     }
                 }
@@ -322,40 +944,90 @@ pub fn target_monster(tcod: &mut Tcod,
     }
 }
 
-pub fn inventory_menu(game: &mut Game, header: &str, root: &mut Root) -> Option<usize> {
-    // show a menu with each item of the inventory as an option
-    let options = if game.inventory.len() == 0 {
+/// how a piece of equipment's bonuses differ from whatever is worn in its slot,
+/// e.g. "+1 power, -1 defense vs sword"
+fn compare_equipment(equipment: Equipment, inventory: &[Object]) -> String {
+    let current = helper::get_equipped_in_slot(equipment.slot, inventory).map(|id| &inventory[id]);
+    let worn = current.and_then(|item| item.equipment);
+    let bonuses = [
+        (equipment.max_hp_bonus - worn.map_or(0, |e| e.max_hp_bonus), "max hp"),
+        (equipment.power_bonus - worn.map_or(0, |e| e.power_bonus), "power"),
+        (equipment.defense_bonus - worn.map_or(0, |e| e.defense_bonus), "defense"),
+        (equipment.regen_bonus - worn.map_or(0, |e| e.regen_bonus), "regen"),
+    ];
+    let changes: Vec<String> = bonuses.iter()
+        .filter(|&&(difference, _)| difference != 0)
+        .map(|&(difference, name)| format!("{:+} {}", difference, name))
+        .collect();
+    let against = current.map_or("nothing".to_string(), |item| item.name.clone());
+    if changes.is_empty() {
+        format!("same as {}", against)
+    } else {
+        format!("{} vs {}", changes.join(", "), against)
+    }
+}
+
+pub fn inventory_menu(game: &mut Game, player: &Object, header: &str, root: &mut Root) -> Option<usize> {
+    loop {
+        // the inventory indices of the items shown, in the order they're listed
+        let view = game.inventory_view;
+        let mut shown: Vec<usize> = (0..game.inventory.len())
+            .filter(|&index| view.filter.shows(&game.inventory[index]))
+            .collect();
+        if view.sorted {
+            shown.sort_by_key(|&index| {
+                let item = &game.inventory[index];
+                (item.equipment.is_some(), helper::item_name(item, game))
+            });
+        }
+
+        let options = inventory_options(game, &shown);
+        let mut header = format!("{}Tab: {}, /: showing {}.\n", header,
+            if view.sorted { "sorted by type" } else { "in pickup order" }, view.filter);
+        // when carrying by weight, show how much of the load is used up
+        if game.settings.carry_by_weight {
+            header += &format!("Carrying {}/{} weight.\n", helper::carried_weight(game), helper::max_carry_weight(player));
+        }
+
+        match menu_key(&header, &options, INVENTORY_WIDTH, root) {
+            (_, Key {code: input::KeyCode::Tab, ..}) => game.inventory_view.sorted = !view.sorted,
+            (_, Key {printable: '/', ..}) => game.inventory_view.filter = view.filter.next(),
+            // map the chosen line back to the item in the inventory
+            (choice, _) => return choice.and_then(|choice| shown.get(choice).cloned()),
+        }
+    }
+}
+
+/// a line for each of the shown inventory items
+fn inventory_options(game: &Game, shown: &[usize]) -> Vec<String> {
+    if game.inventory.len() == 0 {
         vec!["Inventory is empty.".into()]
+    } else if shown.is_empty() {
+        vec!["Nothing to show.".into()]
     } else {
-        game.inventory.iter().map(|item| {
+        shown.iter().map(|&index| &game.inventory[index]).map(|item| {
             // show additional information, in case it's equipped
             match item.equipment {
+                Some(equipment) if equipment.equipped && equipment.cursed => {
+                    format!("{} (on {}, cursed)", item.name, equipment.slot)
+                }
                 Some(equipment) if equipment.equipped => {
                     format!("{} (on {})", item.name, equipment.slot)
                 }
-                _ => item.name.clone()
+                Some(equipment) => {
+                    format!("{} ({})", item.name, compare_equipment(equipment, &game.inventory))
+                }
+                _ if item.count > 1 => format!("{} (x{})", helper::item_name(item, game), item.count),
+                _ if item.charges.is_some() => format!("{} ({} charges)", item.name, item.charges.unwrap()),
+                _ => helper::item_name(item, game)
             }
         }).collect()
-    };
-
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
-
-    // if an item was chosen, return it
-    if game.inventory.len() > 0 {
-        inventory_index
-    } else {
-        None
     }
 }
 
-pub fn initialize_fov(map: &Map, tcod: &mut Tcod) {
+pub fn initialize_fov(game: &Game, tcod: &mut Tcod) {
     // create the FOV map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(x, y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked);
-        }
-    }
+    tcod.fov = crate::fov::new_fov(&game.settings);
+    helper::fill_fov(&mut *tcod.fov, &game.map);
     tcod.con.clear() // unexplored areas start black (which is the default background color)
 }
@@ -5,89 +5,465 @@ use crate::render::*;
 use crate::user_defined::*;
 use tcod::colors::{self};
 
-pub fn cast_heal(_inventory_id: usize, objects: &mut [Object], game: &mut Game, _tcod: &mut Tcod) -> UseResult {
-    // heal the player
-    let player = &mut objects[PLAYER];
-    if let Some(fighter) = player.fighter {
-        if fighter.hp == player.max_hp(game) {
-            game.log.add("You are already at full health.", colors::RED);
+pub fn cast_heal(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    heal(PLAYER, objects, game)
+}
+
+/// heal whoever drinks the potion, the player or a monster
+pub fn heal(caster_id: usize, objects: &mut [Object], game: &mut Game) -> UseResult {
+    let caster = &mut objects[caster_id];
+    if let Some(fighter) = caster.fighter {
+        if fighter.hp == caster.max_hp(game) {
+            if caster_id == PLAYER {
+                game.log.add("You are already at full health.", colors::RED);
+            }
             return UseResult::Cancelled;
         }
-        game.log.add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-        objects[PLAYER].heal(HEAL_AMOUNT, game);
+        if caster_id == PLAYER {
+            game.log.add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
+        }
+        let caster_pos = objects[caster_id].pos();
+        splash_potion(Item::Heal, caster_pos, 0, caster_id, objects, game);
         return UseResult::UsedUp;
     }
     UseResult::Cancelled
 }
 
-pub fn cast_lightning(_inventory_id: usize, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    // find the closest enemy (inside a maximum range) and damage it
-    let monster_id = closest_monster(LIGHTNING_RANGE, objects, tcod);
-    if let Some(monster_id) = monster_id {
+pub fn cast_acid(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    // drinking it is a bad idea, but nothing stops the player
+    game.log.add("You drink the acid. It burns all the way down!", colors::RED);
+    let player_pos = objects[PLAYER].pos();
+    splash_potion(Item::Acid, player_pos, 0, PLAYER, objects, game);
+    UseResult::UsedUp
+}
+
+/// apply a potion's effect to every fighter within the radius of a tile.
+/// drinking a potion is a splash of radius 0 on the player's own tile
+pub fn splash_potion(potion: Item, pos: (i32, i32), radius: i32, caster_id: usize, objects: &mut [Object], game: &mut Game) {
+    let mut xp_to_gain = 0;
+    for (id, obj) in objects.iter_mut().enumerate() {
+        if obj.fighter.is_none() || game_distance(game, obj.pos(), pos) > radius as f32 {
+            continue;
+        }
+        match potion {
+            Item::Heal => {
+                obj.heal(HEAL_AMOUNT, game);
+                if id != PLAYER {
+                    game.log.add(format!("The {} looks healthier.", obj.name), colors::LIGHT_VIOLET);
+                }
+            },
+            Item::Acid => {
+                game.log.add(format!("The {} is burned by acid for {} hit points.", obj.name, ACID_DAMAGE),
+                    colors::LIGHT_GREEN);
+                if let Some(xp) = obj.take_damage(ACID_DAMAGE, game) {
+                    // no reward for splashing yourself
+                    if id != caster_id {
+                        xp_to_gain += xp;
+                    }
+                }
+            },
+            Item::Berserk => {
+                if !obj.has_status(StatusKind::Berserk) {
+                    obj.status_effects.push(StatusEffect{kind: StatusKind::Berserk, turns_left: BERSERK_NUM_TURNS, power: 0});
+                    if id != PLAYER {
+                        game.log.add(format!("The {} flies into a rage!", obj.name), colors::CRIMSON);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    // only the player earns experience
+    if caster_id == PLAYER {
+        if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+            fighter.xp += xp_to_gain;
+        }
+    }
+}
+
+pub fn cast_lightning(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    lightning(PLAYER, objects, game, &tcod.fov)
+}
+
+/// strike the closest fighter to the caster (inside a maximum range) with lightning
+pub fn lightning(caster_id: usize, objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) -> UseResult {
+    let target_id = closest_target(caster_id, LIGHTNING_RANGE, objects, game, fov_map);
+    if let Some(target_id) = target_id {
         // zap it
-        game.log.add(format!("A lighting bolt strikes the {} with a loud BOOM! \
+        let name = if target_id == PLAYER { "you".to_string() } else { format!("the {}", objects[target_id].name) };
+        game.log.add(format!("A lighting bolt strikes {} with a loud BOOM! \
                 The damage is {} hit points.",
-                objects[monster_id].name, LIGHTNING_DAMAGE),
+                name, LIGHTNING_DAMAGE),
             colors::LIGHT_BLUE);
-        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game){
-            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        game.animations.push(Animation::Projectile{from: objects[caster_id].pos(), to: objects[target_id].pos(),
+            char: '*', color: colors::LIGHT_BLUE});
+        if let Some(xp) = objects[target_id].take_damage(LIGHTNING_DAMAGE, game){
+            // only the player earns experience
+            if caster_id == PLAYER {
+                objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+            }
         }
         UseResult::UsedUp
     } else {
         // no enemy found within the maximum range
-        game.log.add("No enemy is close enough to strike.", colors::RED);
+        if caster_id == PLAYER {
+            game.log.add("No enemy is close enough to strike.", colors::RED);
+        }
         UseResult::Cancelled
     }
 }
 
-pub fn cast_confuse(_inventory_id: usize, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) -> UseResult {
+pub fn cast_confuse(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
     // ask the player for a target to confuse
-    game.log.add("Left-click an enemy to confuse it, or right click to cancel.",
+    game.log.add("Left-click an enemy (or move the cursor and press Enter) to confuse it, or right-click/Escape to cancel.",
             colors::LIGHT_CYAN);
     let monster_id = target_monster(tcod, objects, game, Some(CONFUSE_RANGE as f32));
     if let Some(monster_id) = monster_id {
-        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-        // replace the monster's AI with a "confused" one; after
+        if confuse(&mut objects[monster_id]) {
+            game.log.add(format!("The eyes of the {} look vacant, as it starts to stumble around!",
+                    objects[monster_id].name),
+                    colors::LIGHT_GREEN);
+        } else {
+            game.log.add(format!("The {} is unaffected.", objects[monster_id].name), colors::WHITE);
+        }
+        UseResult::UsedUp
+    } else {
+        // no enemy found within max range
+        game.log.add("No enemy is close enough to strike.", colors::RED);
+        UseResult::Cancelled
+    }
+}
+
+/// replace a monster's AI with a "confused" one; after some turns it will restore to the old AI.
+/// returns false, leaving the AI alone, if the monster can't be confused
+fn confuse(monster: &mut Object) -> bool {
+    if monster.is_immune(Immunity::Confusion) {
+        return false;
+    }
+    let old_ai = monster.ai.take().unwrap_or(Ai::basic());
+    monster.ai = Some(Ai::Confused {
+        previous_ai: Box::new(old_ai),
+        num_turns: CONFUSE_NUM_TURNS,
+    });
+    true
+}
+
+pub fn cast_confuse_burst(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // ask the player for a tile to center the burst on
+    game.log.add("Left-click a target tile (or move the cursor and press Enter) for the burst, or right-click/Escape to cancel.",
+        colors::LIGHT_CYAN);
+    let (x, y) = match target_tile(tcod, objects, game, Some(CONFUSE_RANGE as f32), Some(CONFUSE_BURST_RADIUS)) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+
+    let mut confused = 0;
+    for (id, obj) in objects.iter_mut().enumerate() {
+        if id != PLAYER && obj.ai.is_some() && game_distance(game, obj.pos(), (x, y)) <= CONFUSE_BURST_RADIUS as f32 {
+            if confuse(obj) {
+                confused += 1;
+            } else {
+                game.log.add(format!("The {} is unaffected.", obj.name), colors::WHITE);
+            }
+        }
+    }
+    match confused {
+        0 => game.log.add("A dizzying flash goes off, but nobody is caught in it.", colors::LIGHT_GREEN),
+        1 => game.log.add("A dizzying flash leaves 1 monster stumbling around!", colors::LIGHT_GREEN),
+        n => game.log.add(format!("A dizzying flash leaves {} monsters stumbling around!", n), colors::LIGHT_GREEN),
+    }
+    UseResult::UsedUp
+}
+
+pub fn cast_freeze(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // ask the player for a target to freeze
+    game.log.add("Left-click an enemy (or move the cursor and press Enter) to freeze it, or right-click/Escape to cancel.",
+            colors::LIGHT_CYAN);
+    let monster_id = target_monster(tcod, objects, game, Some(FREEZE_RANGE as f32));
+    if let Some(monster_id) = monster_id {
+        if objects[monster_id].is_immune(Immunity::Freeze) {
+            game.log.add(format!("The {} is unaffected.", objects[monster_id].name), colors::WHITE);
+            return UseResult::UsedUp;
+        }
+        let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::basic());
+        // replace the monster's AI with a "frozen" one; after
         // some turns it will restore to the old AI
-        objects[monster_id].ai = Some(Ai::Confused {
+        objects[monster_id].ai = Some(Ai::Frozen {
             previous_ai: Box::new(old_ai),
-            num_turns: CONFUSE_NUM_TURNS,
+            num_turns: FREEZE_NUM_TURNS,
         });
-        game.log.add(format!("The eyes of the {} look vacant, as it starts to stumble around!",
-                objects[monster_id].name),
-                colors::LIGHT_GREEN);
+        game.log.add(format!("The {} is encased in ice!", objects[monster_id].name),
+                colors::LIGHT_BLUE);
         UseResult::UsedUp
     } else {
         // no enemy found within max range
-        game.log.add("No enemy is close enough to strike.", colors::RED);
+        game.log.add("No enemy is close enough to freeze.", colors::RED);
         UseResult::Cancelled
     }
 }
 
-pub fn cast_fireball(_inventory_id: usize, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) -> UseResult {
+pub fn cast_mapping(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // reveal the layout of the level, but not what's in it; nothing gets lit,
+    // so the revealed tiles show in the remembered (dark) colors
+    // solid rock with no floor next to it stays hidden, so only rooms and their walls show up
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            let near_floor = (x - 1..x + 2).any(|nx| (y - 1..y + 2).any(|ny| {
+                nx >= 0 && ny >= 0 && nx < MAP_WIDTH && ny < MAP_HEIGHT && !game.map[nx as usize][ny as usize].blocked
+            }));
+            if near_floor {
+                game.map[x as usize][y as usize].explored = true;
+            }
+        }
+    }
+    game.log.add("The scroll reveals the layout of the level!", colors::LIGHT_YELLOW);
+    // the player didn't move, so redraw the map to show it straight away
+    render_all(tcod, objects, game, true);
+    UseResult::UsedUp
+}
+
+pub fn cast_light(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // ask the player where to conjure a floating ball of light
+    game.log.add("Left-click a tile (or move the cursor and press Enter) to light it up, or right-click/Escape to cancel.",
+        colors::LIGHT_CYAN);
+    let (x, y) = match target_tile(tcod, objects, game, Some(LIGHT_ORB_RANGE), None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+    let mut orb = Object::new(x, y, '*', "glowing orb", colors::LIGHT_YELLOW, false);
+    orb.emitter = Some(Emitter{radius: LIGHT_ORB_RADIUS, color: colors::LIGHT_YELLOW, fuel: LIGHT_ORB_TURNS});
+    orb.lifetime = Some(LIGHT_ORB_TURNS);
+    objects.push(orb);
+    game.log.add("A glowing orb flickers into being, lighting up its surroundings.", colors::LIGHT_YELLOW);
+    // the player didn't move, so redraw with the new light straight away
+    render_all(tcod, objects, game, true);
+    UseResult::UsedUp
+}
+
+pub fn cast_berserk(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    // drive the player into a rage: more power, less defense
+    let player = &mut objects[PLAYER];
+    if player.has_status(StatusKind::Berserk) {
+        game.log.add("You are already raging.", colors::RED);
+        return UseResult::Cancelled;
+    }
+    game.log.add("A red haze clouds your vision. You fly into a berserk rage!", colors::CRIMSON);
+    let player_pos = player.pos();
+    splash_potion(Item::Berserk, player_pos, 0, PLAYER, objects, game);
+    UseResult::UsedUp
+}
+
+pub fn light_torch(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    // the player carries the flame, lighting the tiles around them until it burns out
+    let player = &mut objects[PLAYER];
+    if player.emitter.is_some() {
+        game.log.add("You are already carrying a lit torch.", colors::WHITE);
+        return UseResult::Cancelled;
+    }
+    player.emitter = Some(Emitter{radius: CARRIED_TORCH_RADIUS, color: colors::ORANGE, fuel: CARRIED_TORCH_TURNS});
+    game.log.add("You light the torch. The darkness retreats a little.", colors::ORANGE);
+    UseResult::UsedUp
+}
+
+/// a wand of lightning works just like the scroll, but holds a few charges
+pub fn zap_lightning(inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    match cast_lightning(inventory_id, objects, game, tcod) {
+        UseResult::UsedUp => UseResult::UsedCharge,
+        result => result,
+    }
+}
+
+pub fn dig(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // ask the player for a wall next to them to dig through
+    game.log.add("Left-click a wall next to you (or move the cursor and press Enter) to dig, or right-click/Escape to cancel.",
+        colors::LIGHT_CYAN);
+    let (x, y) = match target_tile(tcod, objects, game, Some(1.5), None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+    let (player_x, player_y) = objects[PLAYER].pos();
+    if (x - player_x).abs() > 1 || (y - player_y).abs() > 1 || !game.map[x as usize][y as usize].blocked {
+        game.log.add("There's no wall there to dig.", colors::WHITE);
+        return UseResult::Cancelled;
+    }
+    // the edge of the map holds everything in
+    if x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1 {
+        game.log.add("The rock here is too hard to dig.", colors::WHITE);
+        return UseResult::Cancelled;
+    }
+
+    // the new floor has been seen, and gets lit (or not) with the rest of the map
+    let mut tile = Tile::empty();
+    tile.explored = true;
+    game.map[x as usize][y as usize] = tile;
+    tcod.fov.set(x, y, true, true);
+    game.log.add("You dig through the wall.", colors::LIGHT_GREY);
+    // redraw with the fov and light recomputed, so the way through shows straight away
+    render_all(tcod, objects, game, true);
+    UseResult::UsedCharge
+}
+
+pub fn eat_ration(_inventory_id: usize, _objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    if game.hunger < RATION_NOURISHMENT / 4 {
+        game.log.add("You're not hungry.", colors::WHITE);
+        return UseResult::Cancelled;
+    }
+    game.hunger = (game.hunger - RATION_NOURISHMENT).max(0);
+    game.log.add("That hit the spot.", colors::LIGHT_GREEN);
+    UseResult::UsedUp
+}
+
+pub fn cast_remove_curse(_inventory_id: usize, _objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    // lift the curse from everything the player has on
+    let mut lifted = 0;
+    for item in game.inventory.iter_mut().filter(|item| item.is_stuck()) {
+        if let Some(ref mut equipment) = item.equipment {
+            equipment.cursed = false;
+            lifted += 1;
+        }
+    }
+    if lifted > 0 {
+        game.log.add("You feel as if someone is watching over you.", colors::LIGHT_CYAN);
+    } else {
+        game.log.add("You feel a faint warmth, but nothing happens.", colors::WHITE);
+    }
+    UseResult::UsedUp
+}
+
+pub fn cast_identify(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    // pick an unknown scroll to learn what it is, without having to read it
+    let identified = &game.identified;
+    let unknown = game.inventory.iter().any(|item| item.item.map_or(false, |kind| kind.is_scroll() && !identified.contains(&kind)));
+    if !unknown {
+        game.log.add("You have nothing left to identify.", colors::WHITE);
+        return UseResult::Cancelled;
+    }
+    let index = match inventory_menu(game, &objects[PLAYER], "Choose a scroll to identify, or any other key to cancel.\n", &mut tcod.root) {
+        Some(index) => index,
+        None => return UseResult::Cancelled,
+    };
+    let kind = game.inventory[index].item;
+    match kind {
+        Some(kind) if kind.is_scroll() && !game.identified.contains(&kind) => {
+            identify(kind, game);
+            game.log.add(format!("It's a {}!", game.inventory[index].name), colors::LIGHT_CYAN);
+            UseResult::UsedUp
+        },
+        _ => {
+            game.log.add("You already know what that is.", colors::WHITE);
+            UseResult::Cancelled
+        },
+    }
+}
+
+pub fn fill_quiver(_inventory_id: usize, _objects: &mut Vec<Object>, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    game.arrows += ARROWS_PER_BUNDLE;
+    game.log.add(format!("You fill your quiver. You have {} arrows.", game.arrows), colors::SEPIA);
+    UseResult::UsedUp
+}
+
+pub fn cast_fireball(_inventory_id: usize, objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) -> UseResult {
     // ask the player for a target tile to throw a fireball at
-    game.log.add("Left-click a target tile for the fireball, or right-click to cancel.",
+    game.log.add("Left-click a target tile (or move the cursor and press Enter) for the fireball, or right-click/Escape to cancel.",
         colors::LIGHT_CYAN);
-    let (x, y) = match target_tile(tcod, objects, game, None) {
+    let (x, y) = match target_tile(tcod, objects, game, None, Some(FIREBALL_RADIUS)) {
         Some(tile_pos) => tile_pos,
         None => return UseResult::Cancelled,
     };
+    fireball(PLAYER, (x, y), objects, game, &tcod.fov)
+}
+
+/// burn everything around a tile, the caster included if it's too close
+pub fn fireball(caster_id: usize, target: (i32, i32), objects: &mut [Object], game: &mut Game, fov_map: &dyn FieldOfView) -> UseResult {
     game.log.add(format!("The fireball exploeds, burning everything within {} tiles!",
             FIREBALL_RADIUS), colors::ORANGE);
 
     let mut xp_to_gain = 0;
     for (id, obj) in objects.iter_mut().enumerate() {
-        if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
+        if game_distance(game, obj.pos(), target) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
             game.log.add(format!("The {} gets burned for {} hit points.",
                 obj.name, FIREBALL_DAMAGE), colors::ORANGE);
             if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
-                // don't reward the player for burning themself!
-                if id != PLAYER {
+                // don't reward the caster for burning themself!
+                if id != caster_id {
                     xp_to_gain += xp;
                 }
             }
         }
     }
-    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+    // only the player earns experience
+    if caster_id == PLAYER {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+    }
+
+    // the blast lights up everything it reaches for a moment, see render_all
+    for x in (target.0 - FIREBALL_RADIUS)..(target.0 + FIREBALL_RADIUS + 1) {
+        for y in (target.1 - FIREBALL_RADIUS)..(target.1 + FIREBALL_RADIUS + 1) {
+            let on_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+            if on_map && game_distance(game, (x, y), target) <= FIREBALL_RADIUS as f32 &&
+                line_of_sight(&game.map, target, (x, y)) {
+                game.light_cache.flash.push((x, y));
+            }
+        }
+    }
+
+    // the blast is loud enough to wake the neighbours
+    wake_monsters(target, FIREBALL_NOISE_RADIUS as f32, objects, game, fov_map);
     UseResult::UsedUp
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::tests::game_with_map;
+    use crate::mapgen::make_monster;
+
+    const ROOM: &[&str] = &[
+        "#######",
+        "#.....#",
+        "#.....#",
+        "#######",
+    ];
+
+    #[test]
+    fn confusing_a_monster_remembers_what_it_was_doing() {
+        let mut orc = make_monster("orc", 2, 2, 1);
+        let ai = orc.ai.clone().unwrap();
+        assert!(confuse(&mut orc));
+        assert_eq!(orc.ai, Some(Ai::Confused{previous_ai: Box::new(ai), num_turns: CONFUSE_NUM_TURNS}));
+    }
+
+    #[test]
+    fn an_immune_monster_cant_be_confused() {
+        let mut wisp = make_monster("will-o-wisp", 2, 2, 1);
+        let ai = wisp.ai.clone();
+        assert!(wisp.is_immune(Immunity::Confusion));
+        assert!(!confuse(&mut wisp));
+        assert_eq!(wisp.ai, ai);
+    }
+
+    #[test]
+    fn a_monster_can_drink_a_healing_potion() {
+        let mut game = game_with_map(ROOM);
+        let mut objects = vec![crate::new_player(), make_monster("orc", 4, 2, 1)];
+        objects[PLAYER].set_pos(1, 1);
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 50;
+        objects[1].fighter.as_mut().unwrap().hp = 5;
+
+        assert_eq!(heal(1, &mut objects, &mut game), UseResult::UsedUp);
+        let max_hp = objects[1].max_hp(&game);
+        assert_eq!(objects[1].fighter.unwrap().hp, (5 + HEAL_AMOUNT).min(max_hp));
+        // only the drinker is healed, and the player isn't told their wounds feel better
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, 50);
+        assert_eq!(game.log.len(), 1);
+        assert_eq!(game.log[0].0, "The orc looks healthier.");
+    }
+
+    #[test]
+    fn a_monster_at_full_health_keeps_its_potion() {
+        let mut game = game_with_map(ROOM);
+        let mut objects = vec![crate::new_player(), make_monster("orc", 4, 2, 1)];
+        assert_eq!(heal(1, &mut objects, &mut game), UseResult::Cancelled);
+        assert!(game.log.is_empty());
+    }
+}
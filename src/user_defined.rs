@@ -1,14 +1,18 @@
 // this file will hold user defined structs, types, enums, etc
+use crate::constants::*;
 use tcod::console::*;
 use tcod::colors::{self, Color};
 use tcod::map::{Map as FovMap}; // the 'Map as FovMap' section renames the tcod fov map
                                 // so that it doesn't conflict with our user defined Map
 use tcod::input::{Mouse};
+use std::collections::{BTreeMap, HashMap};
+use std::cmp;
+use rand::Rng;
 
 /// structs
 // this is a generic object. Anything represented by a character on the screen
 // player, monster, stairs, item, etc
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Object {
     pub x: i32,
     pub y: i32,
@@ -24,7 +28,21 @@ pub struct Object {
     pub level: i32,
     pub equipment: Option<Equipment>,
     pub emitter: Option<Emitter>,
-    pub fov_radius: i32,
+    pub fov_radius: f32, // kept as a float so the eyes can adjust gradually, see sight_radius
+    pub inventory: Vec<Object>, // items carried by a monster, e.g. stolen from the player
+    pub weight: i32,
+    pub status_effects: Vec<StatusEffect>,
+    pub faction: Faction,
+    pub lifetime: Option<i32>, // turns until the object disappears, for temporary things like a light orb
+    pub on_hit: Option<StatusEffect>, // inflicted on whatever this object hurts, e.g. a venomous bite
+    pub trap: Option<Trap>,
+    pub gold: i32, // how much a pile of gold is worth
+    pub count: i32, // how many identical items are stacked in this one, see add_to_inventory
+    pub scavenger: bool, // a monster that picks up potions and gear it walks over, and uses them
+    pub corpse: bool, // the remains of a monster, holding whatever it carried until they rot away
+    pub charges: Option<i32>, // uses left in a wand, which is only used up when they run out
+    pub immunities: Vec<Immunity>, // spell effects that do nothing to this monster
+    pub summoned: bool, // called up by a summoner, and counted against SUMMON_CAP
 }
 
 impl Object {
@@ -44,24 +62,65 @@ impl Object {
             level: 1,
             equipment: None,
             emitter: None,
-            fov_radius: 0,
+            fov_radius: 0.0,
+            inventory: vec![],
+            weight: 0,
+            status_effects: vec![],
+            faction: Faction::Neutral,
+            lifetime: None,
+            on_hit: None,
+            trap: None,
+            gold: 0,
+            count: 1,
+            scavenger: false,
+            corpse: false,
+            charges: None,
+            immunities: vec![],
+            summoned: false,
         }
     }
 
+    /// a trap the player hasn't spotted yet, which isn't drawn or described
+    pub fn is_hidden(&self) -> bool {
+        self.trap.map_or(false, |trap| !trap.detected)
+    }
+
     /// set the color, then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
+    pub fn draw(&self, con: &mut dyn Console) {
+        let color = match self.disabled_tint() {
+            Some((tint, _)) => colors::lerp(self.color, tint, 0.6),
+            None => self.color,
+        };
+        con.set_default_foreground(color);
         con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
     }
 
-    /// set the color to black, then draw the character that represents this object at its position
-    pub fn draw_black(&self, con: &mut Console) {
-        con.set_default_foreground(colors::BLACK);
+    /// draw the character that represents this object in a single color, for when the player is in the dark.
+    /// a disabled monster keeps a faint, washed out hint of its tint
+    pub fn draw_dark(&self, con: &mut dyn Console, color: Color) {
+        let color = match self.disabled_tint() {
+            Some((_, dark_tint)) => colors::lerp(color, dark_tint, 0.5),
+            None => color,
+        };
+        con.set_default_foreground(color);
         con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
     }
 
+    /// the (lit, dark) tints for a monster that can't act normally: frosty when frozen, purple when confused
+    fn disabled_tint(&self) -> Option<(Color, Color)> {
+        match self.ai {
+            Some(Ai::Frozen{..}) => Some((colors::LIGHT_BLUE, colors::DESATURATED_BLUE)),
+            Some(Ai::Confused{..}) => Some((colors::LIGHT_PURPLE, colors::DESATURATED_PURPLE)),
+            _ => None,
+        }
+    }
+
+    pub fn is_immune(&self, immunity: Immunity) -> bool {
+        self.immunities.contains(&immunity)
+    }
+
     /// Erase the character that represents this object
-    pub fn clear(&self, con: &mut Console) {
+    pub fn clear(&self, con: &mut dyn Console) {
         con.put_char(self.x, self.y, ' ', BackgroundFlag::None);
     }
 
@@ -76,40 +135,99 @@ impl Object {
         self.y = y;
     }
 
-    // return the distance to another object
-    pub fn distance_to(&self, other: &Object) -> f32 {
-        let dx = other.x - self.x;
-        let dy = other.y - self.y;
-        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
-    }
-
     pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
         // apply damage if possible
         if let Some(fighter) = self.fighter.as_mut() {
             if damage > 0 {
+                if fighter.on_death == DeathCallback::Player {
+                    game.stats.damage_taken += damage as u32;
+                }
                 fighter.hp -= damage;
+                game.animations.push(Animation::Flash{pos: (self.x, self.y)});
             }
         }
         // check for death, call the death function
         if let Some(fighter) = self.fighter {
             if fighter.hp <= 0 {
                 self.alive = false;
+                if fighter.on_death == DeathCallback::Monster {
+                    *game.stats.kills.entry(self.name.clone()).or_insert(0) += 1;
+                }
                 fighter.on_death.callback(self, game);
                 return Some(fighter.xp);
             }
         }
+        // getting hurt wakes anyone up
+        if damage > 0 && self.wake_up() {
+            game.log.add(format!("The {} wakes up!", self.name), colors::LIGHT_BLUE);
+        }
         None
     }
 
+    /// wake a sleeping monster, returning whether it was actually asleep
+    pub fn wake_up(&mut self) -> bool {
+        match self.ai.take() {
+            Some(Ai::Sleeping{ai_when_awake}) => {
+                self.ai = Some(*ai_when_awake);
+                true
+            },
+            ai => {
+                self.ai = ai;
+                false
+            },
+        }
+    }
+
+    /// whether a monster hasn't noticed the player yet: it's asleep, or it has never
+    /// spotted them (or has given up searching) while they stay in the dark
+    pub fn unaware_of(&self, player: &Object, game: &Game) -> bool {
+        let player_dark = !game.map[player.x as usize][player.y as usize].lit;
+        match self.ai {
+            Some(Ai::Sleeping{..}) => true,
+            Some(Ai::Basic{last_seen: None, ..}) | Some(Ai::Pack{last_seen: None, ..}) => player_dark,
+            _ => false,
+        }
+    }
+
+    /// make a hunting monster aware of where the player is, e.g. after being struck
+    pub fn notice(&mut self, pos: (i32, i32)) {
+        match self.ai {
+            Some(Ai::Basic{ref mut last_seen, ref mut search_turns, ..}) |
+            Some(Ai::Pack{ref mut last_seen, ref mut search_turns, ..}) => {
+                *last_seen = Some(pos);
+                *search_turns = MONSTER_MEMORY_TURNS;
+            },
+            _ => {},
+        }
+    }
+
     pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
         // a simple formula for attack damage
-        let damage = self.power(game) - target.defense(game);
+        let mut damage = self.power(game) - target.defense(game);
+        // the player's first strike on a monster that hasn't noticed them hits much harder
+        let sneak_attack = self.faction == Faction::Player && damage > 0 && target.unaware_of(self, game);
+        if sneak_attack {
+            damage = (damage as f32 * SNEAK_ATTACK_MULTIPLIER).round() as i32;
+            game.log.add("Sneak attack!", colors::LIGHT_VIOLET);
+        }
         if damage > 0 {
             // make the target take some damage
             game.log.add(format!("{} attacks {} for {} hit points", self.name, target.name, damage), colors::WHITE);
-            if let Some(xp) = target.take_damage(damage, game) {
-                // yield experience to the player
+            if self.faction == Faction::Player {
+                game.stats.damage_dealt += damage as u32;
+            }
+            let killed = target.take_damage(damage, game);
+            // getting hit wakes a monster (see take_damage) and tells it where the player is
+            target.notice(self.pos());
+            if let Some(xp) = killed {
+                // yield experience to the player, with a bonus for a kill they never saw coming
+                let xp = if sneak_attack { (xp as f32 * SNEAK_ATTACK_XP_MULTIPLIER).round() as i32 } else { xp };
                 self.fighter.as_mut().unwrap().xp += xp;
+            } else if let Some(effect) = self.on_hit {
+                // a fresh dose replaces whatever is left of the old one
+                target.status_effects.retain(|old| old.kind != effect.kind);
+                target.status_effects.push(effect);
+                game.log.add(format!("{} is {}!", target.name, effect.kind), colors::LIGHT_GREEN);
             }
         } else {
             game.log.add(format!("{} attacks {} but it has no effect", self.name, target.name), colors::WHITE);
@@ -127,11 +245,6 @@ impl Object {
         }
     }
 
-    /// return the distance to some coordinates
-    pub fn distance(&self, x: i32, y: i32) -> f32 {
-        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
-    }
-
     /// equip object and show a message about it
     pub fn equip(&mut self, log: &mut Vec<(String, Color)>) {
         if self.item.is_none() {
@@ -144,6 +257,10 @@ impl Object {
                 equipment.equipped = true;
                 log.add(format!("Equipped {} on {}.", self.name, equipment.slot),
                     colors::LIGHT_GREEN);
+                if equipment.cursed {
+                    log.add(format!("The {} tightens around you. It's cursed!", self.name),
+                        colors::DARK_RED);
+                }
             }
         } else {
             log.add(format!("Can't equip {:?} because it's not an Equipment", self),
@@ -151,13 +268,17 @@ impl Object {
         }
     }
 
-    /// dequip object and show a message about it
-    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) {
+    /// dequip object and show a message about it. returns false if a curse keeps it on
+    pub fn dequip(&mut self, log: &mut Vec<(String, Color)>) -> bool {
         if self.item.is_none() {
             log.add(format!("Can't dequip {:?} because it's not an Item.", self),
                 colors::RED);
-            return
+            return true
         };
+        if self.is_stuck() {
+            log.add(format!("You can't take off the {}, it's cursed!", self.name), colors::RED);
+            return false
+        }
         if let Some(ref mut equipment) = self.equipment {
             if equipment.equipped {
                 equipment.equipped = false;
@@ -168,6 +289,12 @@ impl Object {
             log.add(format!("Can't dequip {:?} because it's not an Equipment.", self),
                 colors::RED);
         }
+        true
+    }
+
+    /// equipped and cursed, so it can't be taken off, dropped or stolen
+    pub fn is_stuck(&self) -> bool {
+        self.equipment.map_or(false, |e| e.equipped && e.cursed)
     }
 
     pub fn max_hp(&self, game: &Game) -> i32 {
@@ -179,13 +306,36 @@ impl Object {
     pub fn power(&self, game: &Game) -> i32 {
         let base_power = self.fighter.map_or(0, |f| f.base_power);
         let bonus = self.get_all_equipped(game).iter().fold(0, |sum, e| sum + e.power_bonus);
-        base_power + bonus
+        let status_bonus = if self.has_status(StatusKind::Berserk) { BERSERK_POWER_BONUS } else { 0 };
+        base_power + bonus + status_bonus
     }
 
     pub fn defense(&self, game: &Game) -> i32 {
         let base_defense = self.fighter.map_or(0, |f| f.base_defense);
         let bonus = self.get_all_equipped(game).iter().fold(0, |sum, e| sum + e.defense_bonus);
-        base_defense + bonus
+        if self.has_status(StatusKind::Berserk) {
+            // rage lowers the guard, but never below nothing at all
+            cmp::max(0, base_defense + bonus - BERSERK_DEFENSE_PENALTY)
+        } else {
+            base_defense + bonus
+        }
+    }
+
+    /// the fov radius rounded to whole tiles, for computing the fov. a dazzled object barely sees at all
+    pub fn sight_radius(&self) -> i32 {
+        let radius = self.fov_radius.round() as i32;
+        if self.has_status(StatusKind::Dazzled) { cmp::min(radius, DAZZLE_RADIUS) } else { radius }
+    }
+
+    /// how quickly the object heals on its own, boosted by equipment
+    pub fn regen_rate(&self, game: &Game) -> i32 {
+        let bonus = self.get_all_equipped(game).iter().fold(0, |sum, e| sum + e.regen_bonus);
+        REGEN_BASE_RATE + bonus
+    }
+
+    /// whether the object is currently under a status effect
+    pub fn has_status(&self, kind: StatusKind) -> bool {
+        self.status_effects.iter().any(|effect| effect.kind == kind)
     }
 
     pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
@@ -198,7 +348,12 @@ impl Object {
                 .map(|item| item.equipment.unwrap())
                 .collect()
         } else {
-            vec![]
+            // monsters wear whatever they've picked up
+            self.inventory
+                .iter()
+                .filter_map(|item| item.equipment)
+                .filter(|equipment| equipment.equipped)
+                .collect()
         }
     }
 
@@ -215,11 +370,57 @@ pub struct Fighter {
     pub xp: i32,
 }
 
-// properties of a light emitter (radius, color)
-#[derive(Debug, Serialize, Deserialize)]
+// a temporary effect on an object, counted down every turn
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub turns_left: i32,
+    pub power: i32, // how strong the effect is, e.g. poison damage per turn
+}
+
+// a spell effect a monster can't be put under, set when it's made
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Immunity {
+    Confusion,
+    Freeze,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StatusKind {
+    Berserk, // more power, less defense, and no backing away from a fight
+    Poison, // loses hp every turn
+    Dazzled, // blinded by stepping into the light with eyes adjusted to the dark
+}
+
+impl std::fmt::Display for StatusKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StatusKind::Berserk => write!(f, "berserk"),
+            StatusKind::Poison => write!(f, "poisoned"),
+            StatusKind::Dazzled => write!(f, "dazzled"),
+        }
+    }
+}
+
+// a trap lying on the floor, sprung when the player steps on it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub detected: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    Spike, // straight damage
+    PoisonDart, // poisons the player
+}
+
+// properties of a light emitter (radius, color, turns of fuel left)
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Emitter {
     pub radius: i32,
     pub color: colors::Color,
+    pub fuel: i32,
 }
 
 // a tile of the map and its properties
@@ -229,18 +430,37 @@ pub struct Tile {
     pub block_sight: bool,
     pub explored: bool,
     pub lit: bool,
+    pub light: f32, // how strongly the tile is lit, from 0.0 (dark) to 1.0 (right next to a torch)
+    pub light_color: Color, // the blended color of every emitter lighting the tile
+    pub terrain: Terrain,
 }
 
 impl Tile {
     pub fn empty() -> Self {
-        Tile{blocked: false, block_sight: false, explored: false, lit: false}
+        Tile{blocked: false, block_sight: false, explored: false, lit: false, light: 0.0, light_color: colors::BLACK, terrain: Terrain::Ground}
     }
 
     pub fn wall() -> Self {
-        Tile{blocked: true, block_sight: true, explored: false, lit: false}
+        Tile{blocked: true, block_sight: true, explored: false, lit: false, light: 0.0, light_color: colors::BLACK, terrain: Terrain::Ground}
+    }
+
+    pub fn water() -> Self {
+        Tile{terrain: Terrain::Water, ..Tile::empty()}
+    }
+
+    pub fn lava() -> Self {
+        Tile{terrain: Terrain::Lava, ..Tile::empty()}
     }
 }
 
+// what the floor of a tile is made of
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Terrain {
+    Ground,
+    Water, // puts out a carried torch
+    Lava, // burns whoever steps in, and monsters won't
+}
+
 // a simple rectangle on the map, used to define a room
 #[derive(Clone, Copy, Debug)]
 pub struct Rect {
@@ -268,6 +488,30 @@ impl Rect {
     }
 }
 
+// monsters that spawn together in a room
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MonsterGroup {
+    OrcWarband, // a warlord and his orcs
+    TrollPair,
+}
+
+impl MonsterGroup {
+    /// the names of the monsters in the group
+    pub fn members(&self) -> &'static [&'static str] {
+        match *self {
+            MonsterGroup::OrcWarband => &["orc warlord", "orc", "orc", "orc"],
+            MonsterGroup::TrollPair => &["troll", "troll"],
+        }
+    }
+}
+
+// how many monsters and items a room gets when the level is populated
+#[derive(Clone, Copy, Debug)]
+pub struct RoomBudget {
+    pub monsters: u32,
+    pub items: u32,
+}
+
 pub struct Transition {
     pub level: u32,
     pub value: u32,
@@ -280,7 +524,36 @@ pub struct Equipment {
     pub equipped: bool,
     pub max_hp_bonus: i32,
     pub power_bonus: i32,
-    pub defense_bonus: i32
+    pub defense_bonus: i32,
+    pub regen_bonus: i32,
+    pub cursed: bool, // can't be taken off once equipped
+}
+
+// counted up over a run, for the character screen and the summary when the run is over
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    pub kills: BTreeMap<String, u32>, // monsters killed by anyone, by name
+    pub items_used: u32,
+    pub steps_dark: u32, // steps the player took onto a dark tile
+    pub steps_lit: u32,
+    pub damage_dealt: u32, // by the player's attacks and arrows
+    pub damage_taken: u32, // by the player, from anything
+}
+
+impl RunStats {
+    /// a few lines to show the stats with, kills listed most first
+    pub fn describe(&self) -> String {
+        let mut kills: Vec<(&String, &u32)> = self.kills.iter().collect();
+        kills.sort_by_key(|&(_, &count)| cmp::Reverse(count));
+        let kills: Vec<String> = kills.iter().map(|&(name, count)| format!("{} x{}", name, count)).collect();
+        let total: u32 = self.kills.values().sum();
+        let kills = if kills.is_empty() { "".to_string() } else { format!(" ({})", kills.join(", ")) };
+        format!("Monsters killed: {}{}
+Damage dealt: {}, taken: {}
+Items used: {}
+Steps in the dark: {}, in the light: {}",
+            total, kills, self.damage_dealt, self.damage_taken, self.items_used, self.steps_dark, self.steps_lit)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -289,14 +562,408 @@ pub struct Game {
     pub log: Messages,
     pub inventory: Vec<Object>,
     pub dungeon_level: u32,
+    pub settings: Settings,
+    pub levels: BTreeMap<u32, Level>, // levels visited before, by dungeon level (not the current one)
+    pub regen_progress: i32, // builds up by the player's regen rate each turn, healing 1 hp when full
+    pub explore_progress: i32, // newly explored tiles not yet turned into experience
+    pub seed: u32, // every level's map is generated from this, so a run can be replayed
+    pub gold: i32,
+    pub arrows: i32, // shot with an equipped bow
+    pub scroll_labels: Vec<(Item, String)>, // what each kind of scroll looks like this game
+    pub identified: Vec<Item>, // scrolls the player knows, by reading them or a scroll of identify
+    pub hunger: i32, // turns' worth of hunger since the player last ate
+    pub difficulty: Difficulty, // chosen at the start, applied to every level generated
+    pub max_depth: Option<u32>, // going down the stairs from this level wins the run. None is endless
+    pub seen_monsters: Vec<String>, // by name, for the bestiary
+    pub seen_items: Vec<Item>,
+    pub turn_count: u32, // turns the player has taken this run, counted in end_turn
+    pub stats: RunStats, // shown on the character screen and when the run is over
+    #[serde(skip)]
+    pub animations: Vec<Animation>, // waiting to be played, see play_animations
+    #[serde(skip)]
+    pub light_cache: LightCache, // rebuilt on demand, see update_lighting
+    #[serde(skip)]
+    pub light_warned: Option<(i32, i32)>, // the lit tile the player was last warned about stepping into
+    #[serde(skip)]
+    pub inventory_view: InventoryView, // how the inventory menu was last sorted and filtered
+}
+
+impl Game {
+    /// a fresh run on the first dungeon level, with nothing carried or seen yet
+    pub fn new(map: Map, settings: Settings, seed: u32, difficulty: Difficulty, max_depth: Option<u32>) -> Self {
+        Game {
+            map: map,
+            // create the list of game messages and their colors, starts empty
+            log: vec![],
+            inventory: vec![],
+            dungeon_level: 1,
+            settings: settings,
+            levels: BTreeMap::new(),
+            regen_progress: 0,
+            explore_progress: 0,
+            seed: seed,
+            animations: vec![],
+            light_cache: LightCache::default(),
+            light_warned: None,
+            inventory_view: InventoryView::default(),
+            gold: 0,
+            arrows: 0,
+            scroll_labels: vec![],
+            identified: vec![],
+            hunger: 0,
+            turn_count: 0,
+            stats: RunStats::default(),
+            difficulty: difficulty,
+            max_depth: max_depth,
+            seen_monsters: vec![],
+            seen_items: vec![],
+        }
+    }
+}
+
+// which objects are on each tile, for code that looks up many tiles while nothing
+// moves, like a path search. it's a snapshot: ids shift as objects come and go, so
+// build a fresh one rather than keeping it around
+pub struct ObjectIndex {
+    tiles: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl ObjectIndex {
+    pub fn new(objects: &[Object]) -> Self {
+        let mut tiles: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (id, object) in objects.iter().enumerate() {
+            tiles.entry(object.pos()).or_insert_with(Vec::new).push(id);
+        }
+        ObjectIndex {tiles: tiles}
+    }
+
+    /// the ids of the objects on a tile, in the same order as the objects list
+    pub fn at(&self, pos: (i32, i32)) -> &[usize] {
+        self.tiles.get(&pos).map_or(&[], |ids| &ids[..])
+    }
+
+    /// the same test as helper::is_blocked, without scanning every object
+    pub fn is_blocked(&self, x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+        map[x as usize][y as usize].blocked || self.at((x, y)).iter().any(|&id| objects[id].blocks)
+    }
+}
+
+// the tiles each emitter lights, remembered until the walls change so a torch
+// only has its fov computed again when it moves or flickers to a new radius
+#[derive(Default)]
+pub struct LightCache {
+    pub level: u32,
+    pub block_sight: Vec<bool>, // the walls the cached fovs were computed against
+    pub fovs: HashMap<(i32, i32, i32), Vec<(i32, i32)>>, // (x, y, radius) -> tiles in the emitter's fov
+    pub lit_tiles: Vec<(i32, i32)>, // tiles that got light last time, to darken before relighting
+    pub afterimages: HashMap<(i32, i32), Afterimage>, // tiles that just went dark, fading out
+    pub flash: Vec<(i32, i32)>, // tiles lit up by an explosion for the next frame only, see render_all
+}
+
+// how a tile looked while it was lit, drawn dimmer every frame until it's gone
+#[derive(Clone, Copy, Debug)]
+pub struct Afterimage {
+    pub light: f32,
+    pub color: Color,
+    pub frames_left: i32,
+}
+
+// a short visual cue for something that happened during a turn
+#[derive(Clone, Copy, Debug)]
+pub enum Animation {
+    Flash{pos: (i32, i32)}, // something got hurt here
+    Projectile{from: (i32, i32), to: (i32, i32), char: char, color: Color}, // something flew along a line
+}
+
+// a level the player has left, kept so they can return to it as they left it
+#[derive(Serialize, Deserialize)]
+pub struct Level {
+    pub map: Map,
+    pub objects: Vec<Object>, // everything but the player
+    pub player_pos: (i32, i32), // where the player left, always a staircase
+}
+
+// player-facing options, picked from the main menu and carried with the game
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub play_prologue: bool,
+    pub touch_sense: bool,
+    // only move up, down, left and right. the diagonal keys do nothing, monsters
+    // step orthogonally, and distances (attack reach, sight, spell ranges and the
+    // fireball radius) are counted in orthogonal steps (manhattan distance)
+    pub orthogonal_movement: bool,
+    // items have a weight, and the player can't pick up more than their strength
+    // allows (on top of the usual 26 item limit)
+    pub carry_by_weight: bool,
+    // dying deletes the save, so there's no going back to it
+    pub permadeath: bool,
+    pub palette: PaletteKind, // the colors the map and panel are drawn in
+    // warn before stepping from the dark into light, which makes the player's eyes adjust
+    pub warn_light: bool,
+    // see with the field of view in fov.rs instead of libtcod's
+    pub shadowcasting: bool,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Settings{
+            play_prologue: PLAY_PROLOGUE_DEFAULT,
+            touch_sense: TOUCH_SENSE_DEFAULT,
+            orthogonal_movement: ORTHOGONAL_MOVEMENT_DEFAULT,
+            carry_by_weight: CARRY_BY_WEIGHT_DEFAULT,
+            permadeath: PERMADEATH_DEFAULT,
+            palette: PaletteKind::Classic,
+            warn_light: WARN_LIGHT_DEFAULT,
+            shadowcasting: SHADOWCASTING_DEFAULT,
+        }
+    }
+}
+
+// which items the inventory menu shows, cycled from inside the menu
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InventoryFilter {
+    All,
+    Usable, // anything that isn't equipment
+    Equipment,
+}
+
+impl InventoryFilter {
+    pub fn next(self) -> InventoryFilter {
+        match self {
+            InventoryFilter::All => InventoryFilter::Usable,
+            InventoryFilter::Usable => InventoryFilter::Equipment,
+            InventoryFilter::Equipment => InventoryFilter::All,
+        }
+    }
+
+    pub fn shows(self, item: &Object) -> bool {
+        match self {
+            InventoryFilter::All => true,
+            InventoryFilter::Usable => item.equipment.is_none(),
+            InventoryFilter::Equipment => item.equipment.is_some(),
+        }
+    }
+}
+
+impl Default for InventoryFilter {
+    fn default() -> Self {
+        InventoryFilter::All
+    }
+}
+
+impl std::fmt::Display for InventoryFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            InventoryFilter::All => write!(f, "all items"),
+            InventoryFilter::Usable => write!(f, "usable items"),
+            InventoryFilter::Equipment => write!(f, "equipment"),
+        }
+    }
+}
+
+// how the inventory menu lists the inventory. only the listing changes, never the
+// inventory's own order, so item indices stay the same for everything else
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InventoryView {
+    pub sorted: bool, // usable items before equipment, then by name. otherwise in pickup order
+    pub filter: InventoryFilter,
+}
+
+// the built-in color palettes, picked in the options menu
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    Classic,
+    HighContrast, // blue and orange instead of red and green, with walls and floors far apart in brightness
+}
+
+impl PaletteKind {
+    pub fn next(self) -> PaletteKind {
+        match self {
+            PaletteKind::Classic => PaletteKind::HighContrast,
+            PaletteKind::HighContrast => PaletteKind::Classic,
+        }
+    }
+
+    pub fn colors(self) -> Palette {
+        match self {
+            PaletteKind::Classic => Palette {
+                dark_wall: COLOR_DARK_WALL,
+                dark_ground: COLOR_DARK_GROUND,
+                light_wall: COLOR_LIGHT_WALL,
+                light_ground: COLOR_LIGHT_GROUND,
+                grey_dark_wall: colors::DARKEST_GREY,
+                grey_dark_ground: colors::DARKER_GREY,
+                grey_light_wall: colors::DARK_GREY,
+                grey_light_ground: colors::GREY,
+                water: COLOR_WATER,
+                lava: COLOR_LAVA,
+                torchlight: None,
+                hp_bar: colors::LIGHT_RED,
+                hp_back: colors::DARKER_RED,
+                dark_object: colors::BLACK,
+            },
+            PaletteKind::HighContrast => Palette {
+                dark_wall: Color{r: 25, g: 25, b: 80},
+                dark_ground: Color{r: 90, g: 90, b: 150},
+                light_wall: Color{r: 100, g: 70, b: 10},
+                light_ground: Color{r: 250, g: 220, b: 120},
+                grey_dark_wall: Color{r: 15, g: 15, b: 15},
+                grey_dark_ground: Color{r: 75, g: 75, b: 75},
+                grey_light_wall: Color{r: 40, g: 40, b: 40},
+                grey_light_ground: Color{r: 170, g: 170, b: 170},
+                water: Color{r: 0, g: 110, b: 255},
+                lava: Color{r: 255, g: 140, b: 0},
+                torchlight: Some(Color{r: 255, g: 240, b: 200}),
+                hp_bar: Color{r: 0, g: 150, b: 255},
+                hp_back: Color{r: 0, g: 40, b: 90},
+                dark_object: colors::BLACK,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for PaletteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PaletteKind::Classic => write!(f, "classic"),
+            PaletteKind::HighContrast => write!(f, "high contrast"),
+        }
+    }
+}
+
+// the colors render_all draws with. the grey ones are used while the player is in the dark
+#[derive(Clone, Copy, Debug)]
+pub struct Palette {
+    pub dark_wall: Color,
+    pub dark_ground: Color,
+    pub light_wall: Color,
+    pub light_ground: Color,
+    pub grey_dark_wall: Color,
+    pub grey_dark_ground: Color,
+    pub grey_light_wall: Color,
+    pub grey_light_ground: Color,
+    pub water: Color,
+    pub lava: Color,
+    pub torchlight: Option<Color>, // None keeps each emitter's own color
+    pub hp_bar: Color,
+    pub hp_back: Color,
+    pub dark_object: Color,
 }
 
 pub struct Tcod {
     pub root: Root,
     pub con: Offscreen,
     pub panel: Offscreen,
-    pub fov: FovMap,
+    pub fov: Box<dyn FieldOfView>, // replaced to match the settings whenever a level is entered, see initialize_fov
     pub mouse: Mouse,
+    pub camera: (i32, i32), // the map position shown in the top-left corner of the screen
+}
+
+// the contents of a save file, tagged with the version of the data model that wrote it
+#[derive(Serialize)]
+pub struct SaveFileRef<'a> {
+    pub version: u32,
+    pub objects: &'a [Object],
+    pub game: &'a Game,
+}
+
+// the version was already checked through SaveHeader, so loading skips it
+#[derive(Deserialize)]
+pub struct SaveFile {
+    pub objects: Vec<Object>,
+    pub game: Game,
+}
+
+// just the version, read first so an old save isn't mistaken for a damaged one
+#[derive(Deserialize)]
+pub struct SaveHeader {
+    pub version: u32,
+}
+
+// everything that can go wrong saving or loading a game
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),          // the file couldn't be read or written
+    Serde(serde_json::Error),    // the game couldn't be turned into json
+    Version(serde_json::Error),  // valid json, but not a game this version understands
+    Corrupt(serde_json::Error),  // the file is damaged or truncated
+    Outdated(u32),               // written by an older version of the game, with this save version
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            SaveError::Io(ref e) => write!(f, "couldn't access the save file: {}", e),
+            SaveError::Serde(ref e) => write!(f, "couldn't write the game: {}", e),
+            SaveError::Version(ref e) => write!(f, "the save is from an incompatible version: {}", e),
+            SaveError::Corrupt(ref e) => write!(f, "the save file is corrupt: {}", e),
+            SaveError::Outdated(version) => write!(f, "the save is from an older version ({}, need {})",
+                version, SAVE_VERSION),
+        }
+    }
+}
+
+impl SaveError {
+    /// a few words on what's wrong with a save, for listing it next to the others
+    pub fn summary(&self) -> &'static str {
+        match *self {
+            SaveError::Io(ref e) if e.kind() == std::io::ErrorKind::NotFound => "empty",
+            SaveError::Io(_) => "can't be read",
+            SaveError::Serde(_) => "can't be written",
+            SaveError::Version(_) => "from an incompatible version",
+            SaveError::Corrupt(_) => "damaged",
+            SaveError::Outdated(_) => "from an older version",
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            SaveError::Io(ref e) => Some(e),
+            SaveError::Serde(ref e) | SaveError::Version(ref e) | SaveError::Corrupt(ref e) => Some(e),
+            SaveError::Outdated(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(error: std::io::Error) -> Self {
+        SaveError::Io(error)
+    }
+}
+
+// everything that can go wrong building a level from a layout file
+#[derive(Debug)]
+pub enum MapFileError {
+    Io(std::io::Error),  // the layout file couldn't be read
+    TooLarge,            // the layout doesn't fit on the map
+    UnknownTile(char),   // a character that isn't one of the known tiles
+}
+
+impl std::fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            MapFileError::Io(ref e) => write!(f, "couldn't read the layout file: {}", e),
+            MapFileError::TooLarge => write!(f, "the layout is larger than the map"),
+            MapFileError::UnknownTile(c) => write!(f, "unknown tile '{}' in the layout", c),
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            MapFileError::Io(ref e) => Some(e),
+            MapFileError::TooLarge | MapFileError::UnknownTile(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MapFileError {
+    fn from(error: std::io::Error) -> Self {
+        MapFileError::Io(error)
+    }
 }
 
 /// types
@@ -305,12 +972,56 @@ pub type Map = Vec<Vec<Tile>>; // a MAP is 2 dimensional vector of tiles
 // a Message is a tuple with 2 fields, message string and its color
 pub type Messages = Vec<(String, Color)>;
 
+// what the bestiary says about each monster, by name
+pub const MONSTER_CATALOG: &[(&str, &str)] = &[
+    ("orc", "Hunts in packs, and picks up anything useful it walks over."),
+    ("orc warlord", "Leads an orc warband. Tougher than the rest, and just as greedy."),
+    ("orc veteran", "An orc that has survived the deep levels. Stronger the deeper it's found."),
+    ("troll", "Slow to anger and hard to kill. Hits very hard."),
+    ("dire troll", "A troll grown huge in the depths. Stronger the deeper it's found."),
+    ("imp", "A thief. Snatches an item and runs for it; catch it before it escapes."),
+    ("will-o-wisp", "Carries its own light, so it can be seen coming across a dark room. Can't be confused or frozen."),
+    ("giant spider", "A weak bite, but a venomous one."),
+    ("goblin archer", "Fragile, but shoots from across the room. Keeps its distance."),
+    ("goblin shaman", "Hangs back and calls up rats to fight for it. Kill it first."),
+    ("rat", "Weak on its own, but rarely on its own."),
+];
+
+// what the bestiary says about each kind of item, with the name it's known by
+pub const ITEM_CATALOG: &[(Item, &str, &str)] = &[
+    (Item::Heal, "healing potion", "Restores some hit points. Shatters and heals everything nearby when thrown."),
+    (Item::Berserk, "potion of berserk rage", "Hit harder for a while, at the cost of defense."),
+    (Item::Acid, "potion of acid", "Burns whoever drinks it or is splashed by it."),
+    (Item::Lightning, "scroll of lightning bolt", "Strikes the closest enemy in range."),
+    (Item::Fireball, "scroll of fireball", "Burns everything around a chosen tile, including you."),
+    (Item::Confuse, "scroll of confuse", "Sends one enemy stumbling around for a while."),
+    (Item::ConfuseBurst, "scroll of confusion burst", "Confuses every enemy around you."),
+    (Item::Freeze, "scroll of freeze", "Freezes an enemy in place for a few turns."),
+    (Item::Mapping, "scroll of magic mapping", "Reveals the layout of the level."),
+    (Item::Light, "scroll of light", "Conjures a floating orb of light."),
+    (Item::Identify, "scroll of identify", "Tells you what an unknown scroll is."),
+    (Item::RemoveCurse, "scroll of remove curse", "Lifts the curse from everything you wear."),
+    (Item::WandLightning, "wand of lightning", "Works like the scroll, for a few charges."),
+    (Item::Dig, "pickaxe", "Digs through a wall next to you. Good for a few walls, but not the edge of the map."),
+    (Item::Torch, "torch", "Light it to see further. Burns down over time."),
+    (Item::Ration, "ration", "Food. Staves off hunger for a long while."),
+    (Item::Sword, "sword", "Held in the hand, for more attack power."),
+    (Item::Shield, "shield", "Held in the hand, for more defense."),
+    (Item::Bow, "bow", "Fire arrows at distant enemies."),
+    (Item::Arrows, "bundle of arrows", "Ammunition for a bow."),
+    (Item::Helmet, "helmet", "Worn on the head, for more defense."),
+    (Item::Armor, "chain mail", "Worn on the body, for a lot more defense."),
+    (Item::Amulet, "amulet of vigor", "Worn around the neck, for more hit points."),
+    (Item::RegenRing, "ring of regeneration", "Worn on a finger, to heal faster."),
+];
+
 /// enums
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PlayerAction {
     TookTurn,
     DidntTakeTurn,
     Exit,
+    Won, // left the dungeon from its deepest level
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -321,6 +1032,69 @@ pub enum Item {
     Fireball,
     Sword,
     Shield,
+    Berserk,
+    Torch,
+    Mapping,
+    Light,
+    Freeze,
+    ConfuseBurst,
+    Acid,
+    RegenRing,
+    Bow,
+    Arrows,
+    Helmet,
+    Armor,
+    Amulet,
+    Identify,
+    RemoveCurse,
+    Ration,
+    WandLightning,
+    Dig,
+}
+
+impl Item {
+    /// potions shatter when thrown, splashing their effect on everything nearby
+    pub fn is_potion(self) -> bool {
+        match self {
+            Item::Heal | Item::Berserk | Item::Acid => true,
+            _ => false,
+        }
+    }
+
+    /// scrolls look alike until they're identified, see item_name
+    pub fn is_scroll(self) -> bool {
+        match self {
+            Item::Lightning | Item::Confuse | Item::Fireball | Item::Mapping | Item::Light |
+            Item::Freeze | Item::ConfuseBurst | Item::Identify | Item::RemoveCurse => true,
+            _ => false,
+        }
+    }
+
+    /// what a shopkeeper asks for it, in gold
+    pub fn price(self) -> i32 {
+        match self {
+            Item::Torch => 10,
+            Item::Heal | Item::Light => 20,
+            Item::Acid => 25,
+            Item::Confuse | Item::Berserk => 30,
+            Item::Freeze => 35,
+            Item::Lightning | Item::Mapping => 40,
+            Item::ConfuseBurst => 45,
+            Item::Fireball => 50,
+            Item::Sword | Item::Shield => 60,
+            Item::RegenRing => 80,
+            Item::Bow => 50,
+            Item::Helmet => 40,
+            Item::Armor => 70,
+            Item::Amulet => 90,
+            Item::Identify => 20,
+            Item::RemoveCurse => 40,
+            Item::Ration => 10,
+            Item::Arrows => 15,
+            Item::WandLightning => 90,
+            Item::Dig => 60,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -328,13 +1102,17 @@ pub enum UseResult {
     UsedUp,
     Cancelled,
     UsedAndKept,
+    UsedCharge, // spent one charge, kept until the last one is gone
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Slot {
     LeftHand,
     RightHand,
-    Head
+    Head,
+    Body,
+    Neck,
+    Finger,
 }
 
 impl std::fmt::Display for Slot {
@@ -343,14 +1121,86 @@ impl std::fmt::Display for Slot {
             Slot::LeftHand => write!(f, "left hand"),
             Slot::RightHand => write!(f, "right hand"),
             Slot::Head => write!(f, "head"),
+            Slot::Body => write!(f, "body"),
+            Slot::Neck => write!(f, "neck"),
+            Slot::Finger => write!(f, "finger"),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Ai {
-    Basic,
+    // path: the way to the player, with the next step last
+    // last_seen: where the player was last spotted, searched for until search_turns runs out
+    Basic{path: Vec<(i32, i32)>, last_seen: Option<(i32, i32)>, search_turns: i32},
     Confused{previous_ai: Box<Ai>, num_turns: i32},
+    Thief,
+    Fleeing,
+    Escaped, // left the level, removed at the end of the turn
+    Ranged, // shoots from a distance and backs off when the player closes in
+    Sleeping{ai_when_awake: Box<Ai>}, // does nothing until the player comes close or makes noise
+    Frozen{previous_ai: Box<Ai>, num_turns: i32}, // skips its turns until it thaws
+    // hunts like Basic, but spreads out with others of its kind to surround the player
+    Pack{path: Vec<(i32, i32)>, last_seen: Option<(i32, i32)>, search_turns: i32},
+    Summoner{cooldown: i32}, // calls up minions while it sees the player, one every SUMMON_COOLDOWN turns
+}
+
+// chosen for a new game, scaling how many monsters there are, how tough they are, and how much healing shows up
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn multiplier(self) -> f32 {
+        match self {
+            Difficulty::Easy => DIFFICULTY_EASY,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => DIFFICULTY_HARD,
+        }
+    }
+
+    /// more of something on harder difficulties, e.g. monsters. Normal leaves it as is
+    pub fn scale(self, value: u32) -> u32 {
+        (value as f32 * self.multiplier()).round() as u32
+    }
+
+    /// less of something on harder difficulties, e.g. healing potions
+    pub fn scale_down(self, value: u32) -> u32 {
+        (value as f32 / self.multiplier()).round() as u32
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Difficulty::Easy => write!(f, "Easy"),
+            Difficulty::Normal => write!(f, "Normal"),
+            Difficulty::Hard => write!(f, "Hard"),
+        }
+    }
+}
+
+// who an object sides with, used to decide what the player is allowed to bump-attack
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Faction {
+    Player,
+    Ally, // swaps places with the player instead of being attacked
+    Neutral, // only attacked after the player confirms
+    Hostile,
+}
+
+impl Ai {
+    /// a basic monster that hasn't spotted the player yet
+    pub fn basic() -> Self {
+        Ai::Basic{path: vec![], last_seen: None, search_turns: 0}
+    }
+
+    pub fn pack() -> Ai {
+        Ai::Pack{path: vec![], last_seen: None, search_turns: 0}
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -382,13 +1232,32 @@ fn player_death(player: &mut Object, game: &mut Game) {
 
 fn monster_death(monster: &mut Object, game: &mut Game) {
     // transform it into a nasty corpse! it doesn't block, can't be attacked, and doesn't move
-    game.log.add(format!("{} is dead! You gain {} experience points.", monster.name, monster.fighter.unwrap().xp), colors::ORANGE);
+    let xp = monster.fighter.unwrap().xp;
+    game.log.add(format!("{} is dead! You gain {} experience points.", monster.name, xp), colors::ORANGE);
     monster.char = '%';
     monster.color = colors::DARK_RED;
     monster.blocks = false;
     monster.fighter = None;
     monster.ai = None;
+    monster.emitter = None; // a glowing monster's light dies with it
+    monster.status_effects.clear();
     monster.name = format!("remains of {}", monster.name);
+    monster.corpse = true;
+    monster.always_visible = true; // remembered where it fell, like an item
+    monster.lifetime = Some(CORPSE_DECAY_TURNS);
+
+    // tougher monsters carry more gold, which spills out of the remains
+    let max_gold = xp / GOLD_PER_XP + 1;
+    let amount = rand::thread_rng().gen_range(1, max_gold + 1);
+    monster.inventory.push(make_gold(amount, monster.x, monster.y));
+}
+
+/// a pile of gold coins, picked up just by walking over it
+pub fn make_gold(amount: i32, x: i32, y: i32) -> Object {
+    let mut pile = Object::new(x, y, '$', "pile of gold", colors::GOLD, false);
+    pile.gold = amount;
+    pile.always_visible = true;
+    pile
 }
 
 // traits
@@ -398,6 +1267,231 @@ pub trait MessageLog {
 
 impl MessageLog for Vec<(String, Color)> {
     fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.push((message.into(), color));
+        let message = message.into();
+        // the same message twice in a row becomes one line with a count, e.g. "The orc attacks you. (x3)",
+        // kept in the color it first showed up in
+        if let Some(&mut (ref mut last, _)) = self.last_mut() {
+            let (text, count) = split_repeat_count(last);
+            if text == message {
+                *last = format!("{} (x{})", message, count + 1);
+                return;
+            }
+        }
+        self.push((message, color));
+    }
+}
+
+/// a logged message and how many times in a row it was added
+fn split_repeat_count(message: &str) -> (&str, u32) {
+    if message.ends_with(')') {
+        if let Some(start) = message.rfind(" (x") {
+            if let Ok(count) = message[start + 3..message.len() - 1].parse() {
+                return (&message[..start], count);
+            }
+        }
+    }
+    (message, 1)
+}
+
+/// which tiles can be seen from a point. the game uses libtcod's fov map, but anything
+/// that answers these can stand in for it, like a different algorithm or a stub
+pub trait FieldOfView {
+    /// see out from (x, y), up to the radius (0 for no limit)
+    fn compute(&mut self, x: i32, y: i32, radius: i32);
+    fn is_in_fov(&self, x: i32, y: i32) -> bool;
+    /// whether light passes through a tile, and whether it can be walked on
+    fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool);
+}
+
+impl FieldOfView for FovMap {
+    fn compute(&mut self, x: i32, y: i32, radius: i32) {
+        self.compute_fov(x, y, radius, FOV_LIGHT_WALLS, FOV_ALGO);
+    }
+
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        FovMap::is_in_fov(self, x, y)
+    }
+
+    fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool) {
+        FovMap::set(self, x, y, transparent, walkable);
+    }
+}
+
+// lets a boxed field of view, like Tcod's, be passed wherever one is expected
+impl FieldOfView for Box<dyn FieldOfView> {
+    fn compute(&mut self, x: i32, y: i32, radius: i32) {
+        (**self).compute(x, y, radius);
+    }
+
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        (**self).is_in_fov(x, y)
+    }
+
+    fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool) {
+        (**self).set(x, y, transparent, walkable);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_messages_collapse_into_a_count() {
+        let mut log: Messages = vec![];
+        log.add("The orc attacks you.", colors::RED);
+        log.add("The orc attacks you.", colors::RED);
+        log.add("The orc attacks you.", colors::RED);
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].0, "The orc attacks you. (x3)");
+    }
+
+    #[test]
+    fn a_repeat_keeps_its_first_color() {
+        let mut log: Messages = vec![];
+        log.add("You hear a noise.", colors::WHITE);
+        log.add("You hear a noise.", colors::RED);
+        assert_eq!(log[0].1, colors::WHITE);
+    }
+
+    #[test]
+    fn only_repeats_in_a_row_collapse() {
+        let mut log: Messages = vec![];
+        log.add("The orc attacks you.", colors::RED);
+        log.add("You attack the orc.", colors::WHITE);
+        log.add("The orc attacks you.", colors::RED);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[2].0, "The orc attacks you.");
+    }
+
+    #[test]
+    fn split_repeat_count_reads_the_count_back() {
+        assert_eq!(split_repeat_count("The orc attacks you. (x12)"), ("The orc attacks you.", 12));
+        assert_eq!(split_repeat_count("The orc attacks you."), ("The orc attacks you.", 1));
+        // brackets that aren't a count are part of the message
+        assert_eq!(split_repeat_count("You found a sword (on the floor)"), ("You found a sword (on the floor)", 1));
+        assert_eq!(split_repeat_count("Odd (xyz)"), ("Odd (xyz)", 1));
+    }
+
+    fn gear(slot: Slot, power_bonus: i32, defense_bonus: i32) -> Object {
+        let mut item = Object::new(0, 0, '[', "gear", colors::SKY, false);
+        item.equipment = Some(Equipment{equipped: true, slot, max_hp_bonus: 0, defense_bonus,
+            power_bonus, regen_bonus: 0, cursed: false});
+        item
+    }
+
+    fn berserk() -> StatusEffect {
+        StatusEffect{kind: StatusKind::Berserk, turns_left: BERSERK_NUM_TURNS, power: 0}
+    }
+
+    #[test]
+    fn berserk_hits_harder_and_guards_less() {
+        let game = Game::new(vec![], Settings::new(), 0, Difficulty::Normal, None);
+        let mut player = crate::new_player();
+        let (power, defense) = (player.power(&game), player.defense(&game));
+        player.status_effects.push(berserk());
+        assert_eq!(player.power(&game), power + BERSERK_POWER_BONUS);
+        // the player's own defense is less than the penalty, and rage never takes it below nothing
+        assert!(defense < BERSERK_DEFENSE_PENALTY);
+        assert_eq!(player.defense(&game), 0);
+    }
+
+    #[test]
+    fn berserk_stacks_with_equipment() {
+        let mut game = Game::new(vec![], Settings::new(), 0, Difficulty::Normal, None);
+        game.inventory.push(gear(Slot::LeftHand, 2, 0));
+        game.inventory.push(gear(Slot::Body, 0, 3));
+        let mut player = crate::new_player();
+        let fighter = player.fighter.unwrap();
+        assert_eq!(player.power(&game), fighter.base_power + 2);
+        assert_eq!(player.defense(&game), fighter.base_defense + 3);
+
+        player.status_effects.push(berserk());
+        assert_eq!(player.power(&game), fighter.base_power + 2 + BERSERK_POWER_BONUS);
+        assert_eq!(player.defense(&game), fighter.base_defense + 3 - BERSERK_DEFENSE_PENALTY);
+
+        // gear that isn't worn doesn't count
+        game.inventory[1].equipment.as_mut().unwrap().equipped = false;
+        assert_eq!(player.defense(&game), 0);
+    }
+
+    #[test]
+    fn a_monster_counts_the_gear_it_carries() {
+        let game = Game::new(vec![], Settings::new(), 0, Difficulty::Normal, None);
+        let mut orc = crate::mapgen::make_monster("orc", 0, 0, 1);
+        let fighter = orc.fighter.unwrap();
+        orc.inventory.push(gear(Slot::LeftHand, 2, 1));
+        orc.status_effects.push(berserk());
+        assert_eq!(orc.power(&game), fighter.base_power + 2 + BERSERK_POWER_BONUS);
+        assert_eq!(orc.defense(&game), cmp::max(0, fighter.base_defense + 1 - BERSERK_DEFENSE_PENALTY));
+    }
+
+    fn json_error() -> serde_json::Error {
+        serde_json::from_str::<u32>("not a number").unwrap_err()
+    }
+
+    #[test]
+    fn a_missing_save_file_is_an_empty_slot() {
+        let error = SaveError::from(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(error, SaveError::Io(_)));
+        assert_eq!(error.summary(), "empty");
+        assert!(error.to_string().starts_with("couldn't access the save file"));
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn an_unreadable_save_file_is_not_empty() {
+        let error = SaveError::Io(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert_eq!(error.summary(), "can't be read");
+        assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[test]
+    fn each_json_error_says_what_went_wrong() {
+        let cases = [
+            (SaveError::Serde(json_error()), "can't be written", "couldn't write the game"),
+            (SaveError::Version(json_error()), "from an incompatible version", "the save is from an incompatible version"),
+            (SaveError::Corrupt(json_error()), "damaged", "the save file is corrupt"),
+        ];
+        for (error, summary, message) in cases.iter() {
+            assert_eq!(error.summary(), *summary);
+            assert!(error.to_string().starts_with(message));
+            // the json error underneath is kept, not just its message
+            let source = std::error::Error::source(error).unwrap();
+            assert_eq!(source.to_string(), json_error().to_string());
+        }
+    }
+
+    #[test]
+    fn an_outdated_save_names_both_versions() {
+        let error = SaveError::Outdated(3);
+        assert_eq!(error.summary(), "from an older version");
+        assert_eq!(error.to_string(), format!("the save is from an older version (3, need {})", SAVE_VERSION));
+        assert!(std::error::Error::source(&error).is_none());
+    }
+
+    #[test]
+    fn normal_difficulty_leaves_values_alone() {
+        for value in 0..20 {
+            assert_eq!(Difficulty::Normal.scale(value), value);
+            assert_eq!(Difficulty::Normal.scale_down(value), value);
+        }
+    }
+
+    #[test]
+    fn harder_difficulties_scale_up_and_heal_less() {
+        assert_eq!(Difficulty::Easy.scale(4), 3);
+        assert_eq!(Difficulty::Hard.scale(4), 5);
+        assert_eq!(Difficulty::Easy.scale_down(3), 4);
+        assert_eq!(Difficulty::Hard.scale_down(5), 4);
+    }
+
+    #[test]
+    fn difficulty_scaling_rounds_to_the_nearest_whole() {
+        // 0.75 * 2 = 1.5 and 1.25 * 2 = 2.5 both round away from zero
+        assert_eq!(Difficulty::Easy.scale(2), 2);
+        assert_eq!(Difficulty::Hard.scale(2), 3);
+        assert_eq!(Difficulty::Hard.scale(1), 1);
+        assert_eq!(Difficulty::Easy.scale(0), 0);
     }
 }